@@ -0,0 +1,392 @@
+use std::fmt::Write;
+
+use crate::protocol::{Arg, ArgKind, Enum, Interface, Message, Protocol};
+
+const KEYWORDS: &[&str] = &[
+	"as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn", "for",
+	"if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+	"self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where",
+	"while", "async", "await", "dyn", "abstract", "become", "box", "do", "final", "macro",
+	"override", "priv", "typeof", "unsized", "virtual", "yield", "try", "gen",
+];
+
+fn ident(name: &str) -> String {
+	if KEYWORDS.contains(&name) {
+		format!("r#{name}")
+	} else {
+		name.to_string()
+	}
+}
+
+fn camel(name: &str) -> String {
+	let mut out = String::new();
+	for part in name.split('_').filter(|p| !p.is_empty()) {
+		let mut chars = part.chars();
+		if let Some(first) = chars.next() {
+			out.push(first.to_ascii_uppercase());
+			out.extend(chars);
+		}
+	}
+	if out.starts_with(|c: char| c.is_ascii_digit()) {
+		out.insert(0, '_');
+	}
+	out
+}
+
+// wl_ is implied for core objects, everything else keeps its prefix
+fn kind_variant(interface: &str) -> String {
+	camel(interface.strip_prefix("wl_").unwrap_or(interface))
+}
+
+fn request_params(arg: &Arg) -> String {
+	let name = ident(&arg.name);
+	match arg.kind {
+		ArgKind::Int => format!("{name}: i32"),
+		ArgKind::UnInt => format!("{name}: u32"),
+		ArgKind::Fixed => format!("{name}: Fixed"),
+		ArgKind::String if arg.allow_null => format!("{name}: Option<&str>"),
+		ArgKind::String => format!("{name}: &str"),
+		ArgKind::Object if arg.allow_null => format!("{name}: Option<Id>"),
+		ArgKind::Object => format!("{name}: Id"),
+		ArgKind::NewId if arg.interface.is_none() => {
+			format!("{0}_interface: &str, {0}_version: u32, {name}: Id", arg.name)
+		}
+		ArgKind::NewId => format!("{name}: Id"),
+		ArgKind::Array => format!("{name}: Vec<u8>"),
+		ArgKind::Fd => format!("{name}: OwnedFd"),
+	}
+}
+
+fn request_arg(arg: &Arg) -> String {
+	let name = ident(&arg.name);
+	match arg.kind {
+		ArgKind::Int => format!("WireArgument::Int({name})"),
+		ArgKind::UnInt => format!("WireArgument::UnInt({name})"),
+		ArgKind::Fixed => format!("WireArgument::FixedPrecision({name})"),
+		ArgKind::String if arg.allow_null => {
+			format!("WireArgument::String({name}.map(String::from))")
+		}
+		ArgKind::String => format!("WireArgument::String(Some(String::from({name})))"),
+		ArgKind::Object if arg.allow_null => {
+			format!("WireArgument::Obj({name}.map_or(0, |id| id.raw()))")
+		}
+		ArgKind::Object => format!("WireArgument::Obj({name}.raw())"),
+		ArgKind::NewId if arg.interface.is_none() => format!(
+			"WireArgument::NewIdSpecific(String::from({0}_interface), {0}_version, {name}.raw())",
+			arg.name
+		),
+		ArgKind::NewId => format!("WireArgument::NewId({name}.raw())"),
+		ArgKind::Array => format!("WireArgument::Arr({name})"),
+		ArgKind::Fd => format!("WireArgument::FileDescriptor({name})"),
+	}
+}
+
+fn event_field_type(arg: &Arg) -> &'static str {
+	match arg.kind {
+		ArgKind::Int => "i32",
+		ArgKind::UnInt => "u32",
+		ArgKind::Fixed => "Fixed",
+		ArgKind::String if arg.allow_null => "Option<String>",
+		ArgKind::String => "String",
+		ArgKind::Object if arg.allow_null => "Option<Id>",
+		ArgKind::Object => "Id",
+		ArgKind::NewId => "Id",
+		ArgKind::Array => "Vec<u8>",
+		ArgKind::Fd => "OwnedFd",
+	}
+}
+
+fn signature_kind(arg: &Arg) -> &'static str {
+	match arg.kind {
+		ArgKind::Int => "WireArgumentKind::Int",
+		ArgKind::UnInt => "WireArgumentKind::UnInt",
+		ArgKind::Fixed => "WireArgumentKind::FixedPrecision",
+		ArgKind::String => "WireArgumentKind::String",
+		ArgKind::Object => "WireArgumentKind::Obj",
+		ArgKind::NewId if arg.interface.is_none() => "WireArgumentKind::NewIdSpecific",
+		ArgKind::NewId => "WireArgumentKind::NewId",
+		ArgKind::Array => "WireArgumentKind::Arr",
+		ArgKind::Fd => "WireArgumentKind::FileDescriptor",
+	}
+}
+
+fn signature_list(out: &mut String, name: &str, msgs: &[Message]) {
+	writeln!(out, "\tpub(crate) const {name}: &[&[WireArgumentKind]] = &[").unwrap();
+	for msg in msgs {
+		let kinds: Vec<&str> = msg.args.iter().map(signature_kind).collect();
+		writeln!(out, "\t\t&[{}],", kinds.join(", ")).unwrap();
+	}
+	writeln!(out, "\t];").unwrap();
+}
+
+fn new_id_list(out: &mut String, name: &str, msgs: &[Message]) {
+	let ifaces: Vec<String> = msgs
+		.iter()
+		.map(|msg| msg.creates().map_or(String::from("None"), |i| format!("Some(\"{i}\")")))
+		.collect();
+	writeln!(out, "\tpub(crate) const {name}: &[Option<&str>] = &[{}];", ifaces.join(", "))
+		.unwrap();
+}
+
+fn gen_request(out: &mut String, opcode: usize, req: &Message) {
+	let mut params = vec![String::from("sender_id: Id")];
+	params.extend(req.args.iter().map(request_params));
+	writeln!(out, "\tpub(crate) fn {}({}) -> WireRequest {{", ident(&req.name), params.join(", "))
+		.unwrap();
+	writeln!(out, "\t\tWireRequest {{").unwrap();
+	writeln!(out, "\t\t\tsender_id,").unwrap();
+	writeln!(out, "\t\t\topcode: OpCode({opcode}),").unwrap();
+	if req.args.is_empty() {
+		writeln!(out, "\t\t\targs: vec![],").unwrap();
+	} else {
+		writeln!(out, "\t\t\targs: vec![").unwrap();
+		for arg in &req.args {
+			writeln!(out, "\t\t\t\t{},", request_arg(arg)).unwrap();
+		}
+		writeln!(out, "\t\t\t],").unwrap();
+	}
+	writeln!(out, "\t\t}}").unwrap();
+	writeln!(out, "\t}}\n").unwrap();
+}
+
+fn gen_events(out: &mut String, events: &[Message]) {
+	writeln!(out, "\t#[derive(Debug)]").unwrap();
+	writeln!(out, "\tpub(crate) enum Event {{").unwrap();
+	for ev in events {
+		if ev.args.is_empty() {
+			writeln!(out, "\t\t{},", camel(&ev.name)).unwrap();
+			continue;
+		}
+		writeln!(out, "\t\t{} {{", camel(&ev.name)).unwrap();
+		for arg in &ev.args {
+			writeln!(out, "\t\t\t{}: {},", ident(&arg.name), event_field_type(arg)).unwrap();
+		}
+		writeln!(out, "\t\t}},").unwrap();
+	}
+	writeln!(out, "\t}}\n").unwrap();
+
+	writeln!(out, "\timpl Event {{").unwrap();
+	writeln!(
+		out,
+		"\t\tpub(crate) fn from_args(opcode: OpCode, args: Vec<WireArgument>) -> Result<Self, WaylandError> {{"
+	)
+	.unwrap();
+	if events.is_empty() {
+		writeln!(out, "\t\t\tlet _ = args;").unwrap();
+		writeln!(out, "\t\t\tErr(WaylandError::InvalidOpCode(opcode.raw(), NAME))").unwrap();
+		writeln!(out, "\t\t}}").unwrap();
+		writeln!(out, "\t}}\n").unwrap();
+		return;
+	}
+	if events.iter().all(|ev| ev.args.is_empty()) {
+		writeln!(out, "\t\t\tlet _ = args;").unwrap();
+	} else {
+		writeln!(out, "\t\t\tlet mut args = args.into_iter();").unwrap();
+	}
+	writeln!(out, "\t\t\tlet event = match opcode.raw() {{").unwrap();
+	for (opcode, ev) in events.iter().enumerate() {
+		if ev.args.is_empty() {
+			writeln!(out, "\t\t\t\t{opcode} => Event::{},", camel(&ev.name)).unwrap();
+			continue;
+		}
+		writeln!(out, "\t\t\t\t{opcode} => Event::{} {{", camel(&ev.name)).unwrap();
+		for arg in &ev.args {
+			writeln!(out, "\t\t\t\t\t{}: next_arg(&mut args)?,", ident(&arg.name)).unwrap();
+		}
+		writeln!(out, "\t\t\t\t}},").unwrap();
+	}
+	writeln!(out, "\t\t\t\tinv => return Err(WaylandError::InvalidOpCode(inv, NAME)),").unwrap();
+	writeln!(out, "\t\t\t}};").unwrap();
+	writeln!(out, "\t\t\tOk(event)").unwrap();
+	writeln!(out, "\t\t}}").unwrap();
+	writeln!(out, "\t}}\n").unwrap();
+}
+
+fn gen_enum(out: &mut String, en: &Enum) {
+	let name = camel(&en.name);
+	writeln!(out, "\t#[repr(u32)]").unwrap();
+	writeln!(out, "\t#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]").unwrap();
+	writeln!(out, "\tpub(crate) enum {name} {{").unwrap();
+	for entry in &en.entries {
+		writeln!(out, "\t\t{} = {:#x},", camel(&entry.name), entry.value).unwrap();
+	}
+	writeln!(out, "\t}}\n").unwrap();
+
+	writeln!(out, "\timpl {name} {{").unwrap();
+	writeln!(out, "\t\tpub(crate) fn from_raw(raw: u32) -> Option<Self> {{").unwrap();
+	writeln!(out, "\t\t\tmatch raw {{").unwrap();
+	for entry in &en.entries {
+		writeln!(out, "\t\t\t\t{:#x} => Some(Self::{}),", entry.value, camel(&entry.name)).unwrap();
+	}
+	writeln!(out, "\t\t\t\t_ => None,").unwrap();
+	writeln!(out, "\t\t\t}}").unwrap();
+	writeln!(out, "\t\t}}\n").unwrap();
+	writeln!(out, "\t\tpub(crate) fn as_str(&self) -> &'static str {{").unwrap();
+	writeln!(out, "\t\t\tmatch self {{").unwrap();
+	for entry in &en.entries {
+		writeln!(out, "\t\t\t\tSelf::{} => \"{}\",", camel(&entry.name), entry.name).unwrap();
+	}
+	writeln!(out, "\t\t\t}}").unwrap();
+	writeln!(out, "\t\t}}").unwrap();
+	writeln!(out, "\t}}\n").unwrap();
+}
+
+fn name_list(msgs: &[Message]) -> String {
+	msgs.iter().map(|m| format!("\"{}\"", m.name)).collect::<Vec<_>>().join(", ")
+}
+
+fn gen_interface(out: &mut String, iface: &Interface) {
+	writeln!(out, "pub(crate) mod {} {{", iface.name).unwrap();
+	writeln!(out, "\tuse super::*;\n").unwrap();
+	writeln!(out, "\tpub(crate) const NAME: &str = \"{}\";", iface.name).unwrap();
+	writeln!(out, "\tpub(crate) const VERSION: u32 = {};", iface.version).unwrap();
+	writeln!(out, "\tpub(crate) const REQUESTS: &[&str] = &[{}];", name_list(&iface.requests))
+		.unwrap();
+	writeln!(out, "\tpub(crate) const EVENTS: &[&str] = &[{}];", name_list(&iface.events)).unwrap();
+	signature_list(out, "REQUEST_SIGNATURES", &iface.requests);
+	signature_list(out, "EVENT_SIGNATURES", &iface.events);
+	new_id_list(out, "REQUEST_NEW_IDS", &iface.requests);
+	new_id_list(out, "EVENT_NEW_IDS", &iface.events);
+	let destructors: Vec<String> =
+		iface.requests.iter().map(|req| req.destructor.to_string()).collect();
+	writeln!(
+		out,
+		"\tpub(crate) const REQUEST_DESTRUCTORS: &[bool] = &[{}];",
+		destructors.join(", ")
+	)
+	.unwrap();
+	for (name, msgs) in [("REQUEST_SINCE", &iface.requests), ("EVENT_SINCE", &iface.events)] {
+		let since: Vec<String> = msgs.iter().map(|msg| msg.since.to_string()).collect();
+		writeln!(out, "\tpub(crate) const {name}: &[u32] = &[{}];", since.join(", ")).unwrap();
+	}
+	writeln!(out).unwrap();
+	for (opcode, req) in iface.requests.iter().enumerate() {
+		gen_request(out, opcode, req);
+	}
+	gen_events(out, &iface.events);
+	for en in &iface.enums {
+		gen_enum(out, en);
+	}
+	writeln!(out, "}}\n").unwrap();
+}
+
+fn gen_kind(out: &mut String, interfaces: &[&Interface]) {
+	writeln!(out, "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]").unwrap();
+	writeln!(out, "pub(crate) enum WaylandObjectKind {{").unwrap();
+	for iface in interfaces {
+		writeln!(out, "\t{},", kind_variant(&iface.name)).unwrap();
+	}
+	writeln!(out, "}}\n").unwrap();
+
+	let arms = |out: &mut String, item: &str| {
+		for iface in interfaces {
+			writeln!(
+				out,
+				"\t\t\tWaylandObjectKind::{} => {}::{item},",
+				kind_variant(&iface.name),
+				iface.name
+			)
+			.unwrap();
+		}
+	};
+
+	writeln!(out, "impl WaylandObjectKind {{").unwrap();
+	writeln!(out, "\tpub(crate) fn as_str(&self) -> &'static str {{").unwrap();
+	writeln!(out, "\t\tmatch self {{").unwrap();
+	arms(out, "NAME");
+	writeln!(out, "\t\t}}").unwrap();
+	writeln!(out, "\t}}\n").unwrap();
+
+	writeln!(out, "\tpub(crate) fn from_interface(name: &str) -> Option<Self> {{").unwrap();
+	writeln!(out, "\t\tmatch name {{").unwrap();
+	for iface in interfaces {
+		writeln!(
+			out,
+			"\t\t\t{}::NAME => Some(WaylandObjectKind::{}),",
+			iface.name,
+			kind_variant(&iface.name)
+		)
+		.unwrap();
+	}
+	writeln!(out, "\t\t\t_ => None,").unwrap();
+	writeln!(out, "\t\t}}").unwrap();
+	writeln!(out, "\t}}\n").unwrap();
+
+	writeln!(out, "\tpub(crate) fn max_version(&self) -> u32 {{").unwrap();
+	writeln!(out, "\t\tmatch self {{").unwrap();
+	arms(out, "VERSION");
+	writeln!(out, "\t\t}}").unwrap();
+	writeln!(out, "\t}}\n").unwrap();
+
+	for (fun, ret, list) in [
+		("request_name", "&'static str", "REQUESTS"),
+		("event_name", "&'static str", "EVENTS"),
+		("request_signature", "&'static [WireArgumentKind]", "REQUEST_SIGNATURES"),
+		("event_signature", "&'static [WireArgumentKind]", "EVENT_SIGNATURES"),
+		("request_since", "u32", "REQUEST_SINCE"),
+		("event_since", "u32", "EVENT_SINCE"),
+	] {
+		writeln!(out, "\tpub(crate) fn {fun}(&self, opcode: OpCode) -> Option<{ret}> {{").unwrap();
+		writeln!(out, "\t\tlet names = match self {{").unwrap();
+		arms(out, list);
+		writeln!(out, "\t\t}};").unwrap();
+		writeln!(out, "\t\tnames.get(opcode.raw() as usize).copied()").unwrap();
+		writeln!(out, "\t}}\n").unwrap();
+	}
+
+	// the kind of object a message brings into existence through its new_id
+	for (fun, list) in [("request_creates", "REQUEST_NEW_IDS"), ("event_creates", "EVENT_NEW_IDS")]
+	{
+		writeln!(out, "\tpub(crate) fn {fun}(&self, opcode: OpCode) -> Option<Self> {{").unwrap();
+		writeln!(out, "\t\tlet ifaces = match self {{").unwrap();
+		arms(out, list);
+		writeln!(out, "\t\t}};").unwrap();
+		writeln!(
+			out,
+			"\t\tifaces.get(opcode.raw() as usize).copied().flatten().and_then(Self::from_interface)"
+		)
+		.unwrap();
+		writeln!(out, "\t}}\n").unwrap();
+	}
+
+	// the name of an error code, from the interface's own error enum
+	writeln!(out, "\tpub(crate) fn error_name(&self, code: u32) -> Option<&'static str> {{")
+		.unwrap();
+	writeln!(out, "\t\tmatch self {{").unwrap();
+	for iface in interfaces.iter().filter(|iface| iface.enums.iter().any(|en| en.name == "error")) {
+		writeln!(
+			out,
+			"\t\t\tWaylandObjectKind::{} => {}::Error::from_raw(code).map(|e| e.as_str()),",
+			kind_variant(&iface.name),
+			iface.name
+		)
+		.unwrap();
+	}
+	writeln!(out, "\t\t\t_ => None,").unwrap();
+	writeln!(out, "\t\t}}").unwrap();
+	writeln!(out, "\t}}\n").unwrap();
+
+	writeln!(out, "\tpub(crate) fn is_destructor(&self, opcode: OpCode) -> bool {{").unwrap();
+	writeln!(out, "\t\tlet destructors = match self {{").unwrap();
+	arms(out, "REQUEST_DESTRUCTORS");
+	writeln!(out, "\t\t}};").unwrap();
+	writeln!(out, "\t\tdestructors.get(opcode.raw() as usize).copied().unwrap_or_default()")
+		.unwrap();
+	writeln!(out, "\t}}").unwrap();
+	writeln!(out, "}}").unwrap();
+}
+
+pub fn generate(protocols: &[Protocol]) -> String {
+	let mut out = String::new();
+	writeln!(out, "// generated by build/main.rs from the files in protocols/, do not edit\n")
+		.unwrap();
+	for proto in protocols {
+		writeln!(out, "// protocol: {}\n", proto.name).unwrap();
+		for iface in &proto.interfaces {
+			gen_interface(&mut out, iface);
+		}
+	}
+	let all: Vec<&Interface> = protocols.iter().flat_map(|p| &p.interfaces).collect();
+	gen_kind(&mut out, &all);
+	out
+}
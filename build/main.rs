@@ -0,0 +1,35 @@
+// generates the protocol bindings included by src/wayland/protocol.rs from
+// the xml files vendored in protocols/. only std is used on purpose
+
+mod codegen;
+mod protocol;
+mod xml;
+
+use std::{env, fs, path::PathBuf};
+
+use crate::protocol::Protocol;
+
+// order matters, interfaces are emitted in this order
+const PROTOCOLS: &[&str] =
+	&["wayland.xml", "xdg-shell.xml", "linux-dmabuf-v1.xml", "viewporter.xml"];
+
+fn main() {
+	println!("cargo::rerun-if-changed=build");
+	println!("cargo::rerun-if-changed=protocols");
+
+	let protocols: Vec<Protocol> = PROTOCOLS
+		.iter()
+		.map(|file| {
+			let path = PathBuf::from("protocols").join(file);
+			let src = fs::read_to_string(&path)
+				.unwrap_or_else(|er| panic!("failed to read {}: {er}", path.display()));
+			xml::parse(&src)
+				.and_then(|root| Protocol::parse(&root))
+				.unwrap_or_else(|er| panic!("failed to parse {}: {er}", path.display()))
+		})
+		.collect();
+
+	let out = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR not set")).join("protocol.rs");
+	fs::write(&out, codegen::generate(&protocols))
+		.unwrap_or_else(|er| panic!("failed to write {}: {er}", out.display()));
+}
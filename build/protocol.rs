@@ -0,0 +1,153 @@
+use crate::xml::Element;
+
+pub struct Protocol {
+	pub name: String,
+	pub interfaces: Vec<Interface>,
+}
+
+pub struct Interface {
+	pub name: String,
+	pub version: u32,
+	pub requests: Vec<Message>,
+	pub events: Vec<Message>,
+	pub enums: Vec<Enum>,
+}
+
+pub struct Message {
+	pub name: String,
+	pub destructor: bool,
+	// the interface version the message first appeared in
+	pub since: u32,
+	pub args: Vec<Arg>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ArgKind {
+	Int,
+	UnInt,
+	Fixed,
+	String,
+	Object,
+	NewId,
+	Array,
+	Fd,
+}
+
+pub struct Arg {
+	pub name: String,
+	pub kind: ArgKind,
+	pub interface: Option<String>,
+	pub allow_null: bool,
+}
+
+pub struct Enum {
+	pub name: String,
+	pub entries: Vec<Entry>,
+}
+
+pub struct Entry {
+	pub name: String,
+	pub value: u32,
+}
+
+fn required<'a>(el: &'a Element, attr: &str) -> Result<&'a str, String> {
+	el.attr(attr).ok_or_else(|| format!("<{}> is missing the {attr:?} attribute", el.name))
+}
+
+fn parse_u32(s: &str) -> Result<u32, String> {
+	let parsed = if let Some(hex) = s.strip_prefix("0x") {
+		u32::from_str_radix(hex, 16)
+	} else {
+		s.parse()
+	};
+	parsed.map_err(|er| format!("bad number {s:?}: {er}"))
+}
+
+impl ArgKind {
+	fn parse(s: &str) -> Result<Self, String> {
+		Ok(match s {
+			"int" => ArgKind::Int,
+			"uint" => ArgKind::UnInt,
+			"fixed" => ArgKind::Fixed,
+			"string" => ArgKind::String,
+			"object" => ArgKind::Object,
+			"new_id" => ArgKind::NewId,
+			"array" => ArgKind::Array,
+			"fd" => ArgKind::Fd,
+			inv => return Err(format!("unknown arg type {inv:?}")),
+		})
+	}
+}
+
+impl Message {
+	// the interface of the object this message creates, if it has a typed
+	// new_id argument
+	pub fn creates(&self) -> Option<&str> {
+		self.args.iter().find(|arg| arg.kind == ArgKind::NewId)?.interface.as_deref()
+	}
+
+	fn parse(el: &Element) -> Result<Self, String> {
+		let args = el
+			.children_named("arg")
+			.map(|arg| {
+				Ok(Arg {
+					name: required(arg, "name")?.to_string(),
+					kind: ArgKind::parse(required(arg, "type")?)?,
+					interface: arg.attr("interface").map(String::from),
+					allow_null: arg.attr("allow-null") == Some("true"),
+				})
+			})
+			.collect::<Result<_, String>>()?;
+		Ok(Self {
+			name: required(el, "name")?.to_string(),
+			destructor: el.attr("type") == Some("destructor"),
+			since: el.attr("since").map(parse_u32).transpose()?.unwrap_or(1),
+			args,
+		})
+	}
+}
+
+impl Enum {
+	fn parse(el: &Element) -> Result<Self, String> {
+		let entries = el
+			.children_named("entry")
+			.map(|entry| {
+				Ok(Entry {
+					name: required(entry, "name")?.to_string(),
+					value: parse_u32(required(entry, "value")?)?,
+				})
+			})
+			.collect::<Result<_, String>>()?;
+		Ok(Self {
+			name: required(el, "name")?.to_string(),
+			entries,
+		})
+	}
+}
+
+impl Interface {
+	fn parse(el: &Element) -> Result<Self, String> {
+		Ok(Self {
+			name: required(el, "name")?.to_string(),
+			version: parse_u32(required(el, "version")?)?,
+			requests: el.children_named("request").map(Message::parse).collect::<Result<_, _>>()?,
+			events: el.children_named("event").map(Message::parse).collect::<Result<_, _>>()?,
+			enums: el.children_named("enum").map(Enum::parse).collect::<Result<_, _>>()?,
+		})
+	}
+}
+
+impl Protocol {
+	pub fn parse(root: &Element) -> Result<Self, String> {
+		if root.name != "protocol" {
+			return Err(format!("root element is <{}>, expected <protocol>", root.name));
+		}
+		Ok(Self {
+			name: required(root, "name")?.to_string(),
+			interfaces: root
+				.children_named("interface")
+				.map(Interface::parse)
+				.collect::<Result<_, _>>()?,
+		})
+	}
+}
@@ -0,0 +1,167 @@
+// a very small xml reader, just enough for the wayland protocol files.
+// text content is thrown away since only the element tree matters
+
+pub struct Element {
+	pub name: String,
+	pub attrs: Vec<(String, String)>,
+	pub children: Vec<Element>,
+}
+
+impl Element {
+	pub fn attr(&self, name: &str) -> Option<&str> {
+		self.attrs.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+	}
+
+	pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+		self.children.iter().filter(move |c| c.name == name)
+	}
+}
+
+struct Reader<'a> {
+	src: &'a str,
+	pos: usize,
+}
+
+impl<'a> Reader<'a> {
+	fn rest(&self) -> &'a str {
+		&self.src[self.pos..]
+	}
+
+	fn skip_past(&mut self, pat: &str) -> Result<(), String> {
+		match self.rest().find(pat) {
+			Some(ix) => {
+				self.pos += ix + pat.len();
+				Ok(())
+			}
+			None => Err(format!("unterminated construct, expected {pat:?}")),
+		}
+	}
+
+	fn skip_ws(&mut self) {
+		let trimmed = self.rest().trim_start();
+		self.pos = self.src.len() - trimmed.len();
+	}
+
+	fn ident(&mut self) -> Result<String, String> {
+		let len = self
+			.rest()
+			.find(|c: char| c.is_whitespace() || c == '=' || c == '>' || c == '/')
+			.unwrap_or(self.rest().len());
+		if len == 0 {
+			return Err(format!("expected a name at byte {}", self.pos));
+		}
+		let ident = self.rest()[..len].to_string();
+		self.pos += len;
+		Ok(ident)
+	}
+
+	fn expect(&mut self, pat: &str) -> Result<(), String> {
+		if self.rest().starts_with(pat) {
+			self.pos += pat.len();
+			Ok(())
+		} else {
+			Err(format!("expected {pat:?} at byte {}", self.pos))
+		}
+	}
+
+	// skips text, comments, declarations and processing instructions until
+	// the next tag. returns false on eof
+	fn skip_misc(&mut self) -> Result<bool, String> {
+		loop {
+			match self.rest().find('<') {
+				None => {
+					self.pos = self.src.len();
+					return Ok(false);
+				}
+				Some(ix) => self.pos += ix,
+			}
+			let rest = self.rest();
+			if rest.starts_with("<!--") {
+				self.skip_past("-->")?;
+			} else if rest.starts_with("<![CDATA[") {
+				self.skip_past("]]>")?;
+			} else if rest.starts_with("<?") {
+				self.skip_past("?>")?;
+			} else if rest.starts_with("<!") {
+				self.skip_past(">")?;
+			} else {
+				return Ok(true);
+			}
+		}
+	}
+
+	fn element(&mut self) -> Result<Element, String> {
+		self.expect("<")?;
+		let name = self.ident()?;
+		let mut attrs = vec![];
+		loop {
+			self.skip_ws();
+			if self.rest().starts_with("/>") {
+				self.pos += 2;
+				return Ok(Element {
+					name,
+					attrs,
+					children: vec![],
+				});
+			}
+			if self.rest().starts_with('>') {
+				self.pos += 1;
+				break;
+			}
+			let key = self.ident()?;
+			self.skip_ws();
+			self.expect("=")?;
+			self.skip_ws();
+			let quote = self.rest().chars().next().ok_or("eof in attribute")?;
+			if quote != '"' && quote != '\'' {
+				return Err(format!("unquoted attribute {key:?}"));
+			}
+			self.pos += 1;
+			let end = self.rest().find(quote).ok_or("unterminated attribute")?;
+			let value = unescape(&self.rest()[..end]);
+			self.pos += end + 1;
+			attrs.push((key, value));
+		}
+
+		let mut children = vec![];
+		loop {
+			if !self.skip_misc()? {
+				return Err(format!("eof inside <{name}>"));
+			}
+			if self.rest().starts_with("</") {
+				self.pos += 2;
+				let closing = self.ident()?;
+				if closing != name {
+					return Err(format!("<{name}> closed by </{closing}>"));
+				}
+				self.skip_ws();
+				self.expect(">")?;
+				return Ok(Element {
+					name,
+					attrs,
+					children,
+				});
+			}
+			children.push(self.element()?);
+		}
+	}
+}
+
+fn unescape(s: &str) -> String {
+	s.replace("&lt;", "<")
+		.replace("&gt;", ">")
+		.replace("&quot;", "\"")
+		.replace("&apos;", "'")
+		.replace("&amp;", "&")
+}
+
+pub fn parse(src: &str) -> Result<Element, String> {
+	let mut rd = Reader {
+		src,
+		pos: 0,
+	};
+	if !rd.skip_misc()? {
+		return Err(String::from("no root element"));
+	}
+	rd.element()
+}
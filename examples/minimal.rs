@@ -14,7 +14,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 		if app.work(&mut state, |_state, ss| {
 			for y in 0..ss.h as usize {
 				for x in 0..ss.w as usize {
-					let ix = (ss.w as usize * y + x) * ss.pf.width() as usize;
+					let ix = (ss.w as usize * y + x) * ss.pf.bytes_per_pixel() as usize;
 					ss.buf[ix] = 0xff;
 					ss.buf[ix + 1] = 0xe4;
 					ss.buf[ix + 2] = 0xff;
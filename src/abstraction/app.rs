@@ -1,14 +1,23 @@
 #![allow(dead_code)]
 #![allow(unused)]
 
-use std::{collections::HashMap, error::Error, marker::PhantomData};
+use std::{
+	collections::{HashMap, VecDeque},
+	error::Error,
+	marker::PhantomData,
+	os::unix::net::UnixStream,
+};
 
+pub use crate::abstraction::window::{SurfaceState, TopLevelWindow};
 use crate::{
-	Rl,
+	NONE, PURPLE, Rl, WaytinierError, rl,
 	wayland::{
-		IdentManager, buffer::BufferBackend, compositor::Compositor, display::Display,
-		registry::Registry, surface::Surface, wire::MessageManager,
+		AppRequest, Id, IdentManager, Raw, SurfaceRegion, Transform, WaylandError,
+		buffer::BufferBackend, callback::Callback, compositor::Compositor, display::Display,
+		protocol::xdg_wm_base, region::Region, registry::Registry, shm::ShmBackend,
+		surface::Surface, wire::MessageManager, xdg_shell::WmBase,
 	},
+	wlog,
 };
 
 pub trait Presenter<B: BufferBackend> {
@@ -19,6 +28,23 @@ pub trait Presenter<B: BufferBackend> {
 	}
 }
 
+// things the app may want to react to, collected while dispatching
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppEvent {
+	// a global showed up, e.g. a monitor was plugged in or a seat appeared.
+	// the ones present at startup are reported too
+	GlobalAdded {
+		name: u32,
+		interface: String,
+		version: u32,
+	},
+	// a global went away, objects bound to it stop working
+	GlobalRemoved {
+		name: u32,
+		interface: String,
+	},
+}
+
 pub(crate) struct PresenterMap<B: BufferBackend, P: Presenter<B>> {
 	pub(crate) last_id: usize,
 	pub(crate) inner: HashMap<usize, P>,
@@ -26,21 +52,478 @@ pub(crate) struct PresenterMap<B: BufferBackend, P: Presenter<B>> {
 	pub(crate) _marker: PhantomData<B>,
 }
 
-pub(crate) struct App<B: BufferBackend, P: Presenter<B>> {
+pub struct App<B: BufferBackend, P: Presenter<B>> {
 	pub(crate) presenters: PresenterMap<B, P>,
+	pub(crate) backend: B,
 	pub(crate) compositor: Rl<Compositor>,
+	// none if the compositor has no xdg-shell, windows can't be made then
+	pub(crate) wm_base: Option<Rl<WmBase>>,
 	pub(crate) registry: Rl<Registry>,
 	pub(crate) display: Rl<Display>,
 	pub finished: bool,
 	pub(crate) wlmm: MessageManager,
 	pub(crate) wlim: IdentManager,
+	pub(crate) events: VecDeque<AppEvent>,
 }
 
 impl<B: BufferBackend, P: Presenter<B>> App<B, P> {
-	pub fn new() -> Result<Self, Box<dyn Error>> {
+	// connects to the compositor found through the environment
+	pub fn new() -> Result<Self, WaytinierError> {
+		Self::connect(MessageManager::from_defualt_env()?)
+	}
+
+	// uses an already connected socket, e.g. one half of a socketpair
+	pub fn from_stream(sock: UnixStream) -> Result<Self, WaytinierError> {
+		Self::connect(MessageManager::from_stream(sock)?)
+	}
+
+	fn connect(wlmm: MessageManager) -> Result<Self, WaytinierError> {
 		let mut wlim = IdentManager::default();
 		let display = Display::new_registered(&mut wlim);
 		let registry = Registry::new_registered(&mut wlim);
-		todo!()
+		let mut app = Self {
+			presenters: PresenterMap {
+				last_id: 0,
+				inner: HashMap::new(),
+				_marker: PhantomData,
+			},
+			backend: B::new(),
+			// replaced once the globals are known
+			compositor: rl!(Compositor::new(Id(0))),
+			wm_base: None,
+			registry,
+			display,
+			finished: false,
+			wlmm,
+			wlim,
+			events: VecDeque::new(),
+		};
+
+		let pending = app.display.borrow().get_registry(app.registry.borrow().id);
+		app.handle_requests(pending)?;
+		// the whole registry burst is in once the sync comes back
+		app.roundtrip()?;
+
+		let (pending, compositor) = Compositor::new_bound(&mut app.wlim, app.registry.clone())?;
+		app.compositor = compositor;
+		app.handle_requests(pending)?;
+		let pending = app.backend.bind(&mut app.wlim, &app.registry)?;
+		app.handle_requests(pending)?;
+		if app.registry.borrow().does_implement(xdg_wm_base::NAME).is_some() {
+			let (pending, wm_base) = WmBase::new_bound(&mut app.wlim, &app.registry)?;
+			app.wm_base = Some(wm_base);
+			app.handle_requests(pending)?;
+		}
+		// globals send their initial state right after the bind, like the
+		// wl_shm formats
+		app.roundtrip()?;
+		Ok(app)
+	}
+
+	// sends a wl_display.sync and dispatches until its callback fires, by
+	// then the compositor has handled every request sent before
+	pub fn roundtrip(&mut self) -> Result<(), WaytinierError> {
+		let cb = Callback::new_registered(&mut self.wlim);
+		let pending = self.display.borrow().sync(cb.borrow().id);
+		self.handle_requests(pending)?;
+		while cb.borrow().done().is_none() {
+			self.dispatch_blocking()?;
+		}
+		Ok(())
+	}
+
+	// dispatches the events that were already read, without touching the
+	// socket for reading. returns how many were dispatched
+	pub fn dispatch_pending(&mut self) -> Result<usize, WaytinierError> {
+		let mut count = 0;
+		while let Some(event) = self.wlmm.next_event(&self.wlim)? {
+			let pending = self.wlim.dispatch(event)?;
+			self.handle_requests(pending)?;
+			count += 1;
+		}
+		self.flush_for_dispatch()?;
+		Ok(count)
+	}
+
+	// flushes, then blocks in poll until at least one event got dispatched
+	pub fn dispatch_blocking(&mut self) -> Result<usize, WaytinierError> {
+		let count = self.dispatch_pending()?;
+		if count > 0 {
+			return Ok(count);
+		}
+		loop {
+			self.flush_for_dispatch()?;
+			self.wlmm.wait(-1)?;
+			self.wlmm.read_socket()?;
+			let count = self.dispatch_pending()?;
+			if count > 0 {
+				return Ok(count);
+			}
+		}
+	}
+
+	// like libwayland, a hangup found while flushing is left for the next
+	// read to report. the wl_display.error that explains it may still be
+	// waiting in the socket
+	fn flush_for_dispatch(&mut self) -> Result<(), WaytinierError> {
+		match self.wlmm.flush() {
+			Err(er) if er.is_disconnect() => Ok(()),
+			res => res.map(drop),
+		}
+	}
+
+	// hands a window over to the app, work draws it from now on. returns an
+	// id for it
+	pub fn push_presenter(&mut self, presenter: P) -> Result<usize, WaytinierError> {
+		let id = self.presenters.last_id;
+		self.presenters.last_id += 1;
+		self.presenters.inner.insert(id, presenter);
+		// the requests that made it shouldn't wait for the next dispatch
+		self.wlmm.flush()?;
+		Ok(id)
+	}
+
+	fn presenter_surface(&self, presenter: usize) -> Result<Rl<Surface>, WaytinierError> {
+		let presenter = self
+			.presenters
+			.inner
+			.get(&presenter)
+			.ok_or(WaylandError::UnknownPresenter(presenter))?;
+		Ok(presenter.surface())
+	}
+
+	// makes a wl_region from the description for set, destroys it right
+	// after. the surface keeps a copy
+	fn set_region(
+		&mut self,
+		presenter: usize,
+		region: Option<&SurfaceRegion>,
+		set: impl FnOnce(&Surface, Option<&Region>) -> Vec<AppRequest>,
+	) -> Result<(), WaytinierError> {
+		let surface = self.presenter_surface(presenter)?;
+		let Some(region) = region else {
+			return self.handle_requests(set(&surface.borrow(), None));
+		};
+		let (mut pending, created) = self.compositor.borrow().create_region(&mut self.wlim);
+		let created = created.borrow();
+		pending.extend(created.fill(region));
+		pending.extend(set(&surface.borrow(), Some(&created)));
+		pending.extend(created.destroy());
+		self.handle_requests(pending)
+	}
+
+	// the part of the presenter that's fully opaque, the compositor can skip
+	// what's behind it. none means nothing is. this and the ones below take
+	// effect with the next frame
+	pub fn set_opaque_region(
+		&mut self,
+		presenter: usize,
+		region: Option<&SurfaceRegion>,
+	) -> Result<(), WaytinierError> {
+		self.set_region(presenter, region, Surface::set_opaque_region)
+	}
+
+	// the part of the presenter that takes pointer and touch input, none
+	// means all of it
+	pub fn set_input_region(
+		&mut self,
+		presenter: usize,
+		region: Option<&SurfaceRegion>,
+	) -> Result<(), WaytinierError> {
+		self.set_region(presenter, region, Surface::set_input_region)
+	}
+
+	// how many buffer pixels make up one surface pixel. the buffer size has
+	// to be a multiple of it, the compositor kills the connection otherwise
+	pub fn set_buffer_scale(&mut self, presenter: usize, scale: i32) -> Result<(), WaytinierError> {
+		let pending = self.presenter_surface(presenter)?.borrow().set_buffer_scale(scale);
+		self.handle_requests(pending)
+	}
+
+	// the transform the buffer contents were drawn with, e.g. the one from
+	// Surface::preferred_buffer_transform
+	pub fn set_buffer_transform(
+		&mut self,
+		presenter: usize,
+		transform: Transform,
+	) -> Result<(), WaytinierError> {
+		let pending = self.presenter_surface(presenter)?.borrow().set_buffer_transform(transform);
+		self.handle_requests(pending)
+	}
+
+	// the oldest event that wasn't looked at yet. events pile up while
+	// dispatching, so drain them after each dispatch call
+	pub fn next_event(&mut self) -> Option<AppEvent> {
+		self.events.pop_front()
+	}
+
+	pub(crate) fn handle_requests(
+		&mut self,
+		pending: Vec<AppRequest>,
+	) -> Result<(), WaytinierError> {
+		for req in pending {
+			match req {
+				AppRequest::Request(req) => {
+					let sender = req.inner.sender_id;
+					let opcode = req.inner.opcode;
+					let kind = self.wlim.check_request(&req.inner)?;
+					// catches requests built for an object whose id got reused
+					debug_assert_eq!(
+						(kind.as_str(), kind.request_name(opcode)),
+						(req.kind, Some(req.opname))
+					);
+					self.wlmm.send_request_logged(req.inner, kind, &self.wlim)?;
+					if kind.is_destructor(opcode) {
+						self.wlim.destroy(sender);
+					}
+				}
+				AppRequest::ProtocolError(object, code, message) => {
+					return Err(self.wlim.protocol_error(object, code, message).into());
+				}
+				AppRequest::IdDeletion(id) => self.wlim.delete_id(id)?,
+				AppRequest::DebugMessage(lvl, msg) => wlog!(lvl, "app", msg, PURPLE, NONE),
+				AppRequest::RegistryPush(name, entry) => {
+					self.events.push_back(AppEvent::GlobalAdded {
+						name: name.raw(),
+						interface: entry.interface.clone(),
+						version: entry.version,
+					});
+					self.registry.borrow_mut().inner.insert(name, entry);
+				}
+				AppRequest::RegistryRemove(name) => {
+					// a remove for a global we never saw is ignored
+					if let Some(entry) = self.registry.borrow_mut().inner.remove(&name) {
+						self.events.push_back(AppEvent::GlobalRemoved {
+							name: name.raw(),
+							interface: entry.interface,
+						});
+					}
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+impl App<ShmBackend, TopLevelWindow> {
+	// one turn of the render loop. waits until some window has something to
+	// do, then every window the compositor sent a frame callback to draws
+	// one. windows that get no callbacks, e.g. because they're hidden, aren't
+	// drawn and don't wake the loop. returns true once every window is closed
+	pub fn work<S, F>(&mut self, state: &mut S, mut draw: F) -> Result<bool, WaytinierError>
+	where
+		F: FnMut(&mut S, &mut SurfaceState),
+	{
+		if self.presenters.inner.is_empty() {
+			self.finished = true;
+			return Ok(true);
+		}
+		if !self.presenters.inner.values().any(|window| window.has_work()) {
+			self.dispatch_blocking()?;
+		}
+
+		let mut pending = vec![];
+		let mut closed = vec![];
+		for (id, window) in &mut self.presenters.inner {
+			if window.toplevel.borrow().close_requested.take() && window.try_close() {
+				closed.push(*id);
+				continue;
+			}
+			pending.extend(window.configure(&self.backend, &mut self.wlim)?);
+			if let Some(time) = window.frame_due() {
+				pending.extend(window.draw(&mut self.wlim, state, time, &mut draw)?);
+			}
+		}
+		for id in closed {
+			if let Some(mut window) = self.presenters.inner.remove(&id) {
+				pending.extend(window.destroy());
+			}
+		}
+		self.handle_requests(pending)?;
+		self.wlmm.flush()?;
+		self.finished = self.presenters.inner.is_empty();
+		Ok(self.finished)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		PixelFormat, ProtocolError,
+		testing::{
+			MockCompositor, WireArgument,
+			fixtures::{TestApp, connect_app},
+		},
+		wayland::{
+			WaylandError, WaylandObjectKind,
+			buffer::BufferBackend,
+			protocol::{wl_compositor, wl_surface},
+			shm::ShmBackend,
+		},
+	};
+
+	#[test]
+	fn connect_binds_after_registry_burst() -> Result<(), Box<dyn Error>> {
+		let (mock, mut app) = connect_app(&[("wl_shm", 1), ("wl_compositor", 6)])?;
+		assert_eq!(app.registry.borrow().does_implement("wl_compositor"), Some(6));
+		assert_eq!(app.registry.borrow().does_implement("wl_shm"), Some(1));
+
+		let labels: Vec<String> = mock.take_requests().iter().map(|req| req.label()).collect();
+		assert_eq!(
+			labels,
+			[
+				"wl_display@1.get_registry",
+				"wl_display@1.sync",
+				"wl_registry@2.bind",
+				"wl_registry@2.bind",
+				"wl_display@1.sync",
+			]
+		);
+		let compositor_id = app.compositor.borrow().id;
+		assert_eq!(mock.object_interface(compositor_id.0), Some("wl_compositor"));
+		let shm_id = app.backend.shm.as_ref().unwrap().borrow().id;
+		assert_eq!(mock.object_interface(shm_id.0), Some("wl_shm"));
+		Ok(())
+	}
+
+	#[test]
+	fn binds_the_lower_version() -> Result<(), Box<dyn Error>> {
+		let (mock, mut app) = connect_app(&[("wl_compositor", 3)])?;
+		assert_eq!(app.compositor.borrow().version, 3);
+		app.roundtrip()?;
+		let bind = mock.take_requests().into_iter().find(|req| req.name == "bind").unwrap();
+		assert!(matches!(bind.args[1], WireArgument::NewIdSpecific(_, 3, _)));
+
+		let (pending, surface) =
+			app.compositor.borrow().make_surface(&mut app.wlim, PixelFormat::Argb8888)?;
+		app.handle_requests(pending)?;
+		let surface_id = surface.borrow().id;
+		assert_eq!(surface.borrow().version, 3);
+		// damage_buffer came with wl_surface version 4
+		let damage = wl_surface::damage_buffer(surface_id, 0, 0, 1, 1);
+		let err = app.wlim.check_request(&damage).unwrap_err();
+		assert!(matches!(err, WaylandError::RequestTooNew("wl_surface", "damage_buffer", 4, 3)));
+		let damage = wl_surface::damage(surface_id, 0, 0, 1, 1);
+		assert!(app.wlim.check_request(&damage).is_ok());
+		Ok(())
+	}
+
+	#[test]
+	fn newer_compositors_are_capped() -> Result<(), Box<dyn Error>> {
+		let (_mock, app) = connect_app(&[("wl_compositor", 99)])?;
+		assert_eq!(app.compositor.borrow().version, wl_compositor::VERSION);
+		Ok(())
+	}
+
+	#[test]
+	fn protocol_errors_are_named() -> Result<(), Box<dyn Error>> {
+		let (mock, mut app) = connect_app(&[("wl_compositor", 6)])?;
+		let (pending, surface) =
+			app.compositor.borrow().make_surface(&mut app.wlim, PixelFormat::Argb8888)?;
+		app.handle_requests(pending)?;
+		let surface_id = surface.borrow().id;
+
+		mock.post_error(surface_id.0, 2, "buffer size not divisible by scale")?;
+		let Err(WaytinierError::Protocol(err)) = app.roundtrip() else {
+			panic!("should be a protocol error");
+		};
+		assert_eq!(
+			err,
+			ProtocolError {
+				object: surface_id.0,
+				interface: Some("wl_surface"),
+				code: 2,
+				name: Some("invalid_size"),
+				message: String::from("buffer size not divisible by scale"),
+			}
+		);
+		assert_eq!(
+			err.to_string(),
+			format!(
+				"wl_surface@{surface_id}: error wl_surface.invalid_size: buffer size not divisible by scale"
+			)
+		);
+		// the compositor hung up after the error
+		assert!(app.roundtrip().unwrap_err().is_disconnect());
+		Ok(())
+	}
+
+	#[test]
+	fn events_for_eventless_objects_are_errors() -> Result<(), Box<dyn Error>> {
+		let (mock, mut app) = connect_app(&[("wl_compositor", 6)])?;
+		let compositor_id = app.compositor.borrow().id;
+		mock.send_event(compositor_id.0, 0, vec![])?;
+		let Err(WaytinierError::Wayland(err)) = app.roundtrip() else {
+			panic!("should be an invalid opcode");
+		};
+		assert!(matches!(err, WaylandError::InvalidOpCode(0, "wl_compositor")));
+		Ok(())
+	}
+
+	#[test]
+	fn missing_compositor_fails() {
+		let (_mock, client) = MockCompositor::spawn(&[("wl_shm", 1)]).unwrap();
+		assert!(TestApp::from_stream(client).is_err());
+	}
+
+	#[test]
+	fn dispatch_pending_doesnt_read() -> Result<(), Box<dyn Error>> {
+		let (mock, mut app) = connect_app(&[("wl_compositor", 6)])?;
+		let registry_id = app.registry.borrow().id;
+
+		let args = vec![
+			WireArgument::UnInt(9),
+			WireArgument::String(Some(String::from("wl_output"))),
+			WireArgument::UnInt(4),
+		];
+		mock.send_event(registry_id.0, 0, args)?;
+		// nothing was read yet, so nothing to dispatch
+		assert_eq!(app.dispatch_pending()?, 0);
+		assert_eq!(app.dispatch_blocking()?, 1);
+		assert_eq!(app.registry.borrow().does_implement("wl_output"), Some(4));
+		Ok(())
+	}
+
+	#[test]
+	fn callback_ids_are_reused() -> Result<(), Box<dyn Error>> {
+		let (_mock, mut app) = connect_app(&[("wl_compositor", 6)])?;
+		app.roundtrip()?;
+		let objects = app.wlim.len();
+		app.roundtrip()?;
+		app.roundtrip()?;
+		// every callback got deleted again
+		assert_eq!(app.wlim.len(), objects);
+		Ok(())
+	}
+
+	#[test]
+	fn globals_come_and_go() -> Result<(), Box<dyn Error>> {
+		let (mock, mut app) = connect_app(&[("wl_compositor", 6)])?;
+		let added = |name: u32, interface: &str, version: u32| AppEvent::GlobalAdded {
+			name,
+			interface: String::from(interface),
+			version,
+		};
+		assert_eq!(app.next_event(), Some(added(1, "wl_compositor", 6)));
+		assert_eq!(app.next_event(), None);
+
+		let output = mock.add_global("wl_output", 4)?;
+		app.roundtrip()?;
+		assert_eq!(app.next_event(), Some(added(output, "wl_output", 4)));
+		assert_eq!(app.registry.borrow().does_implement("wl_output"), Some(4));
+
+		mock.remove_global(output)?;
+		// removing something unknown must not panic or produce an event
+		mock.send_event(app.registry.borrow().id.raw(), 1, vec![WireArgument::UnInt(77)])?;
+		app.roundtrip()?;
+		let removed = AppEvent::GlobalRemoved {
+			name: output,
+			interface: String::from("wl_output"),
+		};
+		assert_eq!(app.next_event(), Some(removed));
+		assert_eq!(app.next_event(), None);
+		assert_eq!(app.registry.borrow().does_implement("wl_output"), None);
+		Ok(())
 	}
 }
@@ -0,0 +1,372 @@
+use crate::{
+	PixelFormat, Rl, WaytinierError,
+	abstraction::{
+		app::{App, Presenter},
+		wizard::TopLevelWindowWizard,
+	},
+	wayland::{
+		AppRequest, IdentManager,
+		buffer::BufferBackend,
+		callback::Callback,
+		shm::ShmBackend,
+		surface::Surface,
+		swapchain::Swapchain,
+		xdg_shell::{XdgSurface, XdgToplevel},
+	},
+};
+
+// used when neither the wizard nor the compositor picked a size
+pub(crate) const DEFAULT_WIDTH: i32 = 640;
+pub(crate) const DEFAULT_HEIGHT: i32 = 480;
+
+// what the draw closure gets, one frame of one window
+pub struct SurfaceState<'a> {
+	// counts the frames drawn for this window, starting at 0
+	pub frame: u64,
+	// from the frame callback, in milliseconds. only the difference between
+	// two frames means anything, 0 for the first frame
+	pub time: u32,
+	pub w: i32,
+	pub h: i32,
+	pub pf: PixelFormat,
+	// w * pf.bytes_per_pixel() bytes per row, no padding
+	pub buf: &'a mut [u8],
+}
+
+// an xdg_toplevel, a regular desktop window
+pub struct TopLevelWindow {
+	pub(crate) surface: Rl<Surface>,
+	pub(crate) xdg_surface: Rl<XdgSurface>,
+	pub(crate) toplevel: Rl<XdgToplevel>,
+	// made on the first configure
+	pub(crate) chain: Option<Swapchain>,
+	pub(crate) width: i32,
+	pub(crate) height: i32,
+	// frames drawn so far
+	pub(crate) frame: u64,
+	// the callback asked for with the last commit, none before the first one
+	pub(crate) frame_cb: Option<Rl<Callback>>,
+	// timestamp of the last frame callback
+	pub(crate) time: u32,
+	pub(crate) close_cb: Option<Box<dyn FnMut() -> bool>>,
+}
+
+impl TopLevelWindow {
+	pub fn spawner<B: BufferBackend, P: Presenter<B>>(
+		parent: &mut App<B, P>,
+	) -> TopLevelWindowWizard<'_, P, B> {
+		TopLevelWindowWizard {
+			app_id: None,
+			title: None,
+			width: None,
+			height: None,
+			parent,
+			close_cb: None,
+			formats: vec![],
+		}
+	}
+
+	// frames aren't drawn before the first configure
+	fn configured(&self) -> bool {
+		self.chain.is_some()
+	}
+
+	// whether work has something to do for this window without waiting on
+	// the compositor
+	pub(crate) fn has_work(&self) -> bool {
+		let frame_due = self.configured()
+			&& self.frame_cb.as_ref().is_none_or(|cb| cb.borrow().done().is_some());
+		frame_due
+			|| self.xdg_surface.borrow().serial.get().is_some()
+			|| self.toplevel.borrow().close_requested.get()
+	}
+
+	// acks a configure that came in and resizes the buffers to match
+	pub(crate) fn configure(
+		&mut self,
+		backend: &ShmBackend,
+		wlim: &mut IdentManager,
+	) -> Result<Vec<AppRequest>, WaytinierError> {
+		let Some(serial) = self.xdg_surface.borrow().take_configure() else {
+			return Ok(vec![]);
+		};
+		// zero leaves the size up to us
+		let (width, height) = self.toplevel.borrow().size.get();
+		if width > 0 {
+			self.width = width;
+		}
+		if height > 0 {
+			self.height = height;
+		}
+		let mut pending = self.xdg_surface.borrow().ack_configure(serial);
+		match &mut self.chain {
+			Some(chain) => pending.extend(chain.resize(self.width, self.height)),
+			None => {
+				let pf = self.surface.borrow().pf;
+				let (created, chain) = Swapchain::new(backend, wlim, self.width, self.height, pf)?;
+				pending.extend(created);
+				self.chain = Some(chain);
+			}
+		}
+		Ok(pending)
+	}
+
+	// the timestamp for the next frame if it should be drawn now. the first
+	// one is drawn right after configure, the rest when the compositor fires
+	// the frame callback of the one before
+	pub(crate) fn frame_due(&mut self) -> Option<u32> {
+		if !self.configured() {
+			return None;
+		}
+		if let Some(cb) = &self.frame_cb {
+			self.time = cb.borrow().done()?;
+			self.frame_cb = None;
+		}
+		Some(self.time)
+	}
+
+	// draws into a free buffer and commits it together with a new frame
+	// callback
+	pub(crate) fn draw<S, F>(
+		&mut self,
+		wlim: &mut IdentManager,
+		state: &mut S,
+		time: u32,
+		draw: &mut F,
+	) -> Result<Vec<AppRequest>, WaytinierError>
+	where
+		F: FnMut(&mut S, &mut SurfaceState),
+	{
+		let Some(chain) = &mut self.chain else {
+			return Ok(vec![]);
+		};
+		let (mut pending, buffer) = chain.acquire(wlim)?;
+		let buffer = buffer.borrow();
+		{
+			let mut canvas = chain.canvas(&buffer);
+			draw(
+				state,
+				&mut SurfaceState {
+					frame: self.frame,
+					time,
+					w: chain.width,
+					h: chain.height,
+					pf: chain.pf,
+					buf: &mut canvas,
+				},
+			);
+		}
+
+		let surface = self.surface.borrow();
+		pending.extend(surface.attach(&buffer, 0, 0));
+		pending.extend(surface.damage_buffer(0, 0, chain.width, chain.height));
+		let (requested, cb) = surface.frame(wlim);
+		pending.extend(requested);
+		pending.extend(surface.commit());
+		self.frame_cb = Some(cb);
+		self.frame += 1;
+		Ok(pending)
+	}
+
+	pub(crate) fn destroy(&mut self) -> Vec<AppRequest> {
+		self.frame_cb = None;
+		let mut pending = self.chain.take().map(|mut chain| chain.destroy()).unwrap_or_default();
+		pending.extend(self.toplevel.borrow().destroy());
+		pending.extend(self.xdg_surface.borrow().destroy());
+		pending.extend(self.surface.borrow().destroy());
+		pending
+	}
+}
+
+impl Presenter<ShmBackend> for TopLevelWindow {
+	fn backend(&self) -> ShmBackend {
+		ShmBackend::new()
+	}
+
+	fn surface(&self) -> Rl<Surface> {
+		self.surface.clone()
+	}
+
+	// asks the close callback, windows without one just close
+	fn try_close(&mut self) -> bool {
+		self.close_cb.as_mut().is_none_or(|cb| cb())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		SurfaceRegion, Transform, WaylandError,
+		testing::{MockCompositor, WireArgument},
+	};
+
+	type WindowApp = App<ShmBackend, TopLevelWindow>;
+
+	// (frame, time, w, h) of every frame drawn
+	type Drawn = Vec<(u64, u32, i32, i32)>;
+
+	fn setup() -> Result<(MockCompositor, WindowApp), WaytinierError> {
+		let globals = [("wl_compositor", 6), ("wl_shm", 2), ("xdg_wm_base", 6)];
+		let (mock, client) = MockCompositor::spawn(&globals)?;
+		let mut app = WindowApp::from_stream(client)?;
+		let window = TopLevelWindow::spawner(&mut app)
+			.with_title("test")
+			.with_width(8)
+			.with_height(4)
+			.spawn()?;
+		app.push_presenter(window)?;
+		Ok((mock, app))
+	}
+
+	fn work(app: &mut WindowApp, drawn: &mut Drawn) -> Result<bool, WaytinierError> {
+		app.work(drawn, |drawn, ss| {
+			ss.buf.fill(0xff);
+			drawn.push((ss.frame, ss.time, ss.w, ss.h));
+		})
+	}
+
+	fn window(app: &WindowApp) -> &TopLevelWindow {
+		app.presenters.inner.values().next().unwrap()
+	}
+
+	#[test]
+	fn frames_follow_the_callbacks() -> Result<(), WaytinierError> {
+		let (mock, mut app) = setup()?;
+		let mut drawn = vec![];
+		for _ in 0..3 {
+			assert!(!work(&mut app, &mut drawn)?);
+		}
+		assert_eq!(drawn, [(0, 0, 8, 4), (1, 16, 8, 4), (2, 32, 8, 4)]);
+
+		app.roundtrip()?;
+		let names: Vec<_> = mock.take_requests().into_iter().map(|req| req.name).collect();
+		let first = names.iter().position(|name| *name == "ack_configure").unwrap();
+		assert_eq!(
+			names[first..first + 7],
+			[
+				"ack_configure",
+				"create_pool",
+				"create_buffer",
+				"attach",
+				"damage_buffer",
+				"frame",
+				"commit"
+			]
+		);
+		Ok(())
+	}
+
+	#[test]
+	fn roundtrips_dont_take_frame_callbacks() -> Result<(), WaytinierError> {
+		let (mock, mut app) = setup()?;
+		let mut drawn = vec![];
+		work(&mut app, &mut drawn)?;
+		mock.take_requests();
+		// the fired frame callback got deleted, the syncs reuse its id
+		app.roundtrip()?;
+		app.roundtrip()?;
+		let syncs = mock.take_requests().into_iter().filter(|req| req.name == "sync").count();
+		assert_eq!(syncs, 2);
+		work(&mut app, &mut drawn)?;
+		assert_eq!(drawn, [(0, 0, 8, 4), (1, 16, 8, 4)]);
+		Ok(())
+	}
+
+	#[test]
+	fn occluded_windows_arent_drawn() -> Result<(), WaytinierError> {
+		let (mock, mut app) = setup()?;
+		mock.set_occluded(true)?;
+		let mut drawn = vec![];
+		work(&mut app, &mut drawn)?;
+		app.roundtrip()?;
+		// nothing to do, work would block
+		assert!(!window(&app).has_work());
+		assert_eq!(drawn.len(), 1);
+
+		mock.set_occluded(false)?;
+		work(&mut app, &mut drawn)?;
+		assert_eq!(drawn, [(0, 0, 8, 4), (1, 16, 8, 4)]);
+		Ok(())
+	}
+
+	#[test]
+	fn configure_resizes() -> Result<(), WaytinierError> {
+		let (mock, mut app) = setup()?;
+		let mut drawn = vec![];
+		work(&mut app, &mut drawn)?;
+		let (xdg_surface, toplevel) = {
+			let window = window(&app);
+			(window.xdg_surface.borrow().id.0, window.toplevel.borrow().id.0)
+		};
+		let args = vec![WireArgument::Int(16), WireArgument::Int(0), WireArgument::Arr(vec![])];
+		mock.send_event(toplevel, 0, args)?;
+		mock.send_event(xdg_surface, 0, vec![WireArgument::UnInt(77)])?;
+		app.roundtrip()?;
+		work(&mut app, &mut drawn)?;
+		// a zero height keeps the old one
+		assert_eq!(drawn[1], (1, 16, 16, 4));
+		app.roundtrip()?;
+		let ack = mock.take_requests().into_iter().rfind(|req| req.name == "ack_configure");
+		assert!(matches!(ack.unwrap().args[..], [WireArgument::UnInt(77)]));
+		Ok(())
+	}
+
+	#[test]
+	fn close_asks_the_callback() -> Result<(), WaytinierError> {
+		let (mock, mut app) = setup()?;
+		let mut drawn = vec![];
+		work(&mut app, &mut drawn)?;
+		let (surface, toplevel) = {
+			let window = window(&app);
+			(window.surface.borrow().id.0, window.toplevel.borrow().id.0)
+		};
+		let mut vetoes = 1;
+		app.presenters.inner.values_mut().next().unwrap().close_cb = Some(Box::new(move || {
+			vetoes -= 1;
+			vetoes < 0
+		}));
+
+		mock.send_event(toplevel, 1, vec![])?;
+		app.roundtrip()?;
+		assert!(!work(&mut app, &mut drawn)?);
+		mock.send_event(toplevel, 1, vec![])?;
+		app.roundtrip()?;
+		assert!(work(&mut app, &mut drawn)?);
+		assert!(app.finished);
+		app.roundtrip()?;
+		assert_eq!(mock.object_interface(surface), None);
+		assert_eq!(mock.object_interface(toplevel), None);
+		Ok(())
+	}
+
+	#[test]
+	fn surface_settings_reach_the_compositor() -> Result<(), WaytinierError> {
+		let (mock, mut app) = setup()?;
+		let opaque = SurfaceRegion::new().add(0, 0, 8, 4).subtract(2, 2, 1, 1);
+		app.set_opaque_region(0, Some(&opaque))?;
+		app.set_input_region(0, None)?;
+		app.set_buffer_transform(0, Transform::Flipped90)?;
+		assert!(matches!(
+			app.set_buffer_scale(1, 2),
+			Err(WaytinierError::Wayland(WaylandError::UnknownPresenter(1)))
+		));
+		app.roundtrip()?;
+
+		let names: Vec<_> = mock.take_requests().into_iter().map(|req| req.name).collect();
+		let first = names.iter().position(|name| *name == "create_region").unwrap();
+		assert_eq!(
+			names[first..first + 7],
+			[
+				"create_region",
+				"add",
+				"subtract",
+				"set_opaque_region",
+				"destroy",
+				"set_input_region",
+				"set_buffer_transform"
+			]
+		);
+		Ok(())
+	}
+}
@@ -1,7 +1,10 @@
 use crate::{
-	Rl,
-	abstraction::app::{App, Presenter},
-	wayland::{buffer::BufferBackend, shm::ShmBackend, surface::Surface},
+	PixelFormat, WaytinierError,
+	abstraction::{
+		app::{App, Presenter},
+		window::{DEFAULT_HEIGHT, DEFAULT_WIDTH, TopLevelWindow},
+	},
+	wayland::{WaylandError, buffer::BufferBackend, protocol::xdg_wm_base, shm::ShmBackend},
 };
 
 pub struct TopLevelWindowWizard<'a, P: Presenter<B>, B: BufferBackend = ShmBackend> {
@@ -9,10 +12,10 @@ pub struct TopLevelWindowWizard<'a, P: Presenter<B>, B: BufferBackend = ShmBacke
 	pub(crate) title: Option<String>,
 	pub(crate) width: Option<i32>,
 	pub(crate) height: Option<i32>,
-	pub(crate) sur: Option<Rl<Surface>>,
 	pub(crate) parent: &'a mut App<B, P>,
 	pub(crate) close_cb: Option<Box<dyn FnMut() -> bool>>,
-	pub(crate) backend: Option<B>,
+	// in order of preference, the first one the compositor has is used
+	pub(crate) formats: Vec<PixelFormat>,
 }
 
 impl<P: Presenter<B>, B: BufferBackend> TopLevelWindowWizard<'_, P, B> {
@@ -36,6 +39,13 @@ impl<P: Presenter<B>, B: BufferBackend> TopLevelWindowWizard<'_, P, B> {
 		self
 	}
 
+	// asks for a pixel format. can be called more than once, formats asked
+	// for earlier win. argb8888 is used if the compositor has none of them
+	pub fn with_pixel_format(mut self, pf: PixelFormat) -> Self {
+		self.formats.push(pf);
+		self
+	}
+
 	pub fn with_close_callback<F>(mut self, cb: F) -> Self
 	where
 		F: FnMut() -> bool + 'static,
@@ -44,3 +54,42 @@ impl<P: Presenter<B>, B: BufferBackend> TopLevelWindowWizard<'_, P, B> {
 		self
 	}
 }
+
+impl<P: Presenter<ShmBackend>> TopLevelWindowWizard<'_, P, ShmBackend> {
+	// creates the window. it shows up once the compositor configured it and
+	// the first frame is drawn, see App::work
+	pub fn spawn(self) -> Result<TopLevelWindow, WaytinierError> {
+		let app = self.parent;
+		let wm_base = app.wm_base.clone().ok_or(WaylandError::NotInRegistry(xdg_wm_base::NAME))?;
+		let pf = app.backend.pick_format(&self.formats);
+		let (pending, surface) = app.compositor.borrow().make_surface(&mut app.wlim, pf)?;
+		app.handle_requests(pending)?;
+		let (pending, xdg_surface) =
+			wm_base.borrow().get_xdg_surface(&mut app.wlim, &surface.borrow());
+		app.handle_requests(pending)?;
+		let (pending, toplevel) = xdg_surface.borrow().get_toplevel(&mut app.wlim);
+		app.handle_requests(pending)?;
+		if let Some(title) = &self.title {
+			app.handle_requests(toplevel.borrow().set_title(title))?;
+		}
+		if let Some(app_id) = &self.app_id {
+			app.handle_requests(toplevel.borrow().set_app_id(app_id))?;
+		}
+		// a commit without a buffer, the compositor answers it with the first
+		// configure
+		app.handle_requests(surface.borrow().commit())?;
+
+		Ok(TopLevelWindow {
+			surface,
+			xdg_surface,
+			toplevel,
+			chain: None,
+			width: self.width.unwrap_or(DEFAULT_WIDTH),
+			height: self.height.unwrap_or(DEFAULT_HEIGHT),
+			frame: 0,
+			frame_cb: None,
+			time: 0,
+			close_cb: self.close_cb,
+		})
+	}
+}
@@ -0,0 +1,124 @@
+use std::{error::Error, fmt::Display, io, string::FromUtf8Error};
+
+use crate::wayland::{ProtocolError, WaylandError};
+
+// everything that can go wrong in waytinier, so callers can tell a dead
+// compositor from a protocol error or a missing global
+#[derive(Debug)]
+pub enum WaytinierError {
+	// talking to the compositor broke, the socket is most likely gone
+	Io(io::Error),
+	// the compositor sent wl_display.error, the connection is dead after it
+	Protocol(ProtocolError),
+	// the compositor or the caller did something waytinier can't handle
+	Wayland(WaylandError),
+	// a string from the wire wasn't utf-8
+	Utf8(FromUtf8Error),
+}
+
+impl WaytinierError {
+	// true when the compositor can't be talked to anymore
+	pub fn is_disconnect(&self) -> bool {
+		match self {
+			WaytinierError::Io(er) => matches!(
+				er.kind(),
+				io::ErrorKind::BrokenPipe
+					| io::ErrorKind::ConnectionReset
+					| io::ErrorKind::ConnectionAborted
+					| io::ErrorKind::UnexpectedEof
+			),
+			WaytinierError::Wayland(WaylandError::ConnectionClosed) => true,
+			_ => false,
+		}
+	}
+}
+
+impl Error for WaytinierError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		// the cause is only given here and never repeated in Display, so
+		// printing the chain shows every message once
+		match self {
+			WaytinierError::Io(er) => Some(er),
+			WaytinierError::Protocol(er) => Some(er),
+			WaytinierError::Wayland(er) => Some(er),
+			WaytinierError::Utf8(er) => Some(er),
+		}
+	}
+}
+
+impl Display for WaytinierError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			WaytinierError::Io(_) => write!(f, "i/o error on the wayland connection"),
+			WaytinierError::Protocol(_) => write!(f, "the compositor sent a protocol error"),
+			WaytinierError::Wayland(_) => write!(f, "waytinier couldn't do what was asked"),
+			WaytinierError::Utf8(_) => write!(f, "compositor sent a string that isn't utf-8"),
+		}
+	}
+}
+
+impl From<io::Error> for WaytinierError {
+	fn from(er: io::Error) -> Self {
+		WaytinierError::Io(er)
+	}
+}
+
+impl From<ProtocolError> for WaytinierError {
+	fn from(er: ProtocolError) -> Self {
+		WaytinierError::Protocol(er)
+	}
+}
+
+impl From<WaylandError> for WaytinierError {
+	fn from(er: WaylandError) -> Self {
+		WaytinierError::Wayland(er)
+	}
+}
+
+impl From<FromUtf8Error> for WaytinierError {
+	fn from(er: FromUtf8Error) -> Self {
+		WaytinierError::Utf8(er)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::WireArgumentKind;
+
+	// what a chain printer like anyhow's {:#} shows
+	fn chain(er: &dyn Error) -> String {
+		let mut out = er.to_string();
+		let mut cur = er.source();
+		while let Some(er) = cur {
+			out += &format!(": {er}");
+			cur = er.source();
+		}
+		out
+	}
+
+	#[test]
+	fn sources_chain() {
+		let er = WaytinierError::from(io::Error::from(io::ErrorKind::BrokenPipe));
+		assert!(er.is_disconnect());
+		assert_eq!(chain(&er), "i/o error on the wayland connection: broken pipe");
+
+		let er = WaytinierError::from(WaylandError::NotInRegistry("wl_shm"));
+		assert!(!er.is_disconnect());
+		assert_eq!(
+			chain(&er),
+			"waytinier couldn't do what was asked: wl_shm not found in registry"
+		);
+
+		let mismatch = WaylandError::ArgumentMismatch(WireArgumentKind::Int, WireArgumentKind::Arr);
+		let er = WaytinierError::from(mismatch);
+		assert_eq!(er.source().unwrap().to_string(), "expected an argument of type Int, got Arr");
+
+		let utf8 = String::from_utf8(vec![0xff]).unwrap_err();
+		let er = WaytinierError::from(utf8);
+		assert_eq!(
+			chain(&er),
+			"compositor sent a string that isn't utf-8: invalid utf-8 sequence of 1 bytes from index 0"
+		);
+	}
+}
@@ -1,14 +1,17 @@
-#![feature(unix_socket_ancillary_data)]
-
-use std::{
-	cell::RefCell,
-	rc::{Rc, Weak},
-	sync::OnceLock,
-};
+use std::{cell::RefCell, rc::Rc, sync::OnceLock};
 
 pub mod abstraction;
+mod error;
 pub(crate) mod wayland;
 
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+pub use error::WaytinierError;
+pub use wayland::{
+	Fixed, PixelFormat, ProtocolError, SurfaceRegion, Transform, WaylandError, WireArgumentKind,
+};
+
 // restructuring
 // - object should not have refs to god.
 // - remove dependency on god object - wltos funcalls should return
@@ -79,7 +82,6 @@ macro_rules! dbug {
 }
 
 pub(crate) type Rl<T> = Rc<RefCell<T>>;
-pub(crate) type Wl<T> = Weak<RefCell<T>>;
 
 #[macro_export]
 macro_rules! rl {
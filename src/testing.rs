@@ -0,0 +1,689 @@
+// a tiny fake compositor for tests. it serves one end of a socket pair on its
+// own thread, advertises the globals it was given, answers wl_display.sync,
+// configures xdg toplevels, fires frame callbacks on commit and records every
+// request it receives, so the client side can be exercised without a real
+// compositor or WAYLAND_DISPLAY
+
+use std::{
+	collections::{HashMap, VecDeque},
+	io,
+	net::Shutdown,
+	os::{
+		fd::{AsRawFd, OwnedFd, RawFd},
+		unix::net::UnixStream,
+	},
+	sync::{
+		Arc, Mutex,
+		atomic::{AtomicBool, Ordering},
+	},
+	thread::{self, JoinHandle},
+};
+
+pub use crate::wayland::wire::WireArgument;
+use crate::wayland::{
+	OpCode, WaylandObjectKind,
+	protocol::wl_display,
+	wire::{WireArgumentKind, decode_args, poll_fd, recv_with_fds, send_with_fds},
+};
+
+// how long the server thread sleeps in poll before checking if it should stop
+const POLL_MS: i32 = 10;
+
+// a request as the compositor saw it
+#[derive(Debug)]
+pub struct RecordedRequest {
+	pub object: u32,
+	pub interface: &'static str,
+	pub opcode: u16,
+	pub name: &'static str,
+	pub args: Vec<WireArgument>,
+}
+
+impl RecordedRequest {
+	// interface@id.name, like in WAYLAND_DEBUG output
+	pub fn label(&self) -> String {
+		format!("{}@{}.{}", self.interface, self.object, self.name)
+	}
+}
+
+struct Shared {
+	// write end, shared with send_event
+	sock: UnixStream,
+	// name, interface, version
+	globals: Vec<(u32, String, u32)>,
+	next_name: u32,
+	objects: HashMap<u32, WaylandObjectKind>,
+	requests: Vec<RecordedRequest>,
+	serial: u32,
+	// wl_shm format codes announced on every wl_shm bind
+	shm_formats: Vec<u32>,
+	// frame callbacks by surface, fired on the surface's next commit
+	frames: HashMap<u32, Vec<u32>>,
+	// fired callbacks waiting for the surfaces to become visible again
+	held: Vec<u32>,
+	occluded: bool,
+	// the frame callback timestamp, one 60hz refresh further every time
+	clock: u32,
+	// wl_surface -> (xdg_surface, configured yet)
+	xdg_surfaces: HashMap<u32, (u32, bool)>,
+	// xdg_surface -> xdg_toplevel
+	toplevels: HashMap<u32, u32>,
+	// set once a protocol error went out, nothing is handled after that
+	closed: bool,
+}
+
+pub struct MockCompositor {
+	shared: Arc<Mutex<Shared>>,
+	stop: Arc<AtomicBool>,
+	thread: Option<JoinHandle<()>>,
+}
+
+impl MockCompositor {
+	// starts the server thread. globals get the names 1, 2, 3, ... in the
+	// order given. the returned stream is the client end
+	pub fn spawn(globals: &[(&str, u32)]) -> io::Result<(Self, UnixStream)> {
+		let (client, server) = UnixStream::pair()?;
+		let shared = Arc::new(Mutex::new(Shared {
+			sock: server.try_clone()?,
+			globals: (1..)
+				.zip(globals)
+				.map(|(name, (iface, ver))| (name, iface.to_string(), *ver))
+				.collect(),
+			next_name: globals.len() as u32 + 1,
+			objects: HashMap::from([(1, WaylandObjectKind::Display)]),
+			requests: vec![],
+			serial: 0,
+			// the two every compositor has
+			shm_formats: vec![0, 1],
+			frames: HashMap::new(),
+			held: vec![],
+			occluded: false,
+			clock: 0,
+			xdg_surfaces: HashMap::new(),
+			toplevels: HashMap::new(),
+			closed: false,
+		}));
+		let stop = Arc::new(AtomicBool::new(false));
+		let thread = {
+			let shared = shared.clone();
+			let stop = stop.clone();
+			thread::spawn(move || serve(server, shared, stop))
+		};
+		Ok((
+			Self {
+				shared,
+				stop,
+				thread: Some(thread),
+			},
+			client,
+		))
+	}
+
+	// everything received since the last call
+	pub fn take_requests(&self) -> Vec<RecordedRequest> {
+		std::mem::take(&mut self.shared.lock().unwrap().requests)
+	}
+
+	// the interface the compositor thinks the object has, if it's alive
+	pub fn object_interface(&self, id: u32) -> Option<&'static str> {
+		self.shared.lock().unwrap().objects.get(&id).map(|kind| kind.as_str())
+	}
+
+	// advertises a new global to every registry, returns its name
+	pub fn add_global(&self, interface: &str, version: u32) -> io::Result<u32> {
+		let mut shared = self.shared.lock().unwrap();
+		let name = shared.next_name;
+		shared.next_name += 1;
+		shared.globals.push((name, interface.to_string(), version));
+		for registry in shared.registries() {
+			shared.send_global(registry, name, interface, version)?;
+		}
+		Ok(name)
+	}
+
+	// withdraws a global from every registry
+	pub fn remove_global(&self, name: u32) -> io::Result<()> {
+		let mut shared = self.shared.lock().unwrap();
+		shared.globals.retain(|(n, _, _)| *n != name);
+		for registry in shared.registries() {
+			shared.send(registry, 1, vec![WireArgument::UnInt(name)])?;
+		}
+		Ok(())
+	}
+
+	// replaces the formats wl_shm announces, for binds that come later
+	pub fn set_shm_formats(&self, formats: &[u32]) {
+		self.shared.lock().unwrap().shm_formats = formats.to_vec();
+	}
+
+	// an occluded compositor holds back frame callbacks, like one where the
+	// windows are hidden. the held ones fire once it's visible again
+	pub fn set_occluded(&self, occluded: bool) -> io::Result<()> {
+		let mut shared = self.shared.lock().unwrap();
+		shared.occluded = occluded;
+		if occluded {
+			return Ok(());
+		}
+		let held = std::mem::take(&mut shared.held);
+		shared.fire_frames(held)
+	}
+
+	// sends wl_display.error and hangs up, like a real compositor does
+	pub fn post_error(&self, object: u32, code: u32, message: &str) -> io::Result<()> {
+		self.shared.lock().unwrap().post_error(object, code, message.to_string())
+	}
+
+	// sends an arbitrary event to the client
+	pub fn send_event(&self, object: u32, opcode: u16, args: Vec<WireArgument>) -> io::Result<()> {
+		self.shared.lock().unwrap().send(object, opcode, args)
+	}
+}
+
+impl Drop for MockCompositor {
+	fn drop(&mut self) {
+		self.stop.store(true, Ordering::Relaxed);
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+	}
+}
+
+impl Shared {
+	fn send(&mut self, object: u32, opcode: u16, args: Vec<WireArgument>) -> io::Result<()> {
+		self.send_all(vec![(object, opcode, args)])
+	}
+
+	// (object, opcode, args) messages in a single write, so the client sees
+	// all of them or none
+	fn send_all(&mut self, messages: Vec<(u32, u16, Vec<WireArgument>)>) -> io::Result<()> {
+		let mut buf = vec![];
+		let mut fds: Vec<OwnedFd> = vec![];
+		for (object, opcode, args) in messages {
+			let start = buf.len();
+			buf.extend([0; 8]);
+			for arg in args {
+				match arg {
+					WireArgument::FileDescriptor(fd) => fds.push(fd),
+					other => buf.extend(other.as_vec_u8()),
+				}
+			}
+			let word2 = (((buf.len() - start) as u32) << 16) | opcode as u32;
+			buf[start..start + 4].copy_from_slice(&object.to_ne_bytes());
+			buf[start + 4..start + 8].copy_from_slice(&word2.to_ne_bytes());
+		}
+
+		let raw: Vec<RawFd> = fds.iter().map(|fd| fd.as_raw_fd()).collect();
+		let mut sent = 0;
+		while sent < buf.len() {
+			let fds = if sent == 0 {
+				raw.as_slice()
+			} else {
+				&[]
+			};
+			match send_with_fds(&self.sock, &buf[sent..], fds) {
+				Ok(n) => sent += n,
+				Err(er) if er.kind() == io::ErrorKind::WouldBlock => {
+					poll_fd(&self.sock, libc::POLLOUT, -1)?;
+				}
+				Err(er) => return Err(er),
+			}
+		}
+		Ok(())
+	}
+
+	// wl_callback.done and the delete_id that follows it go out together,
+	// like libwayland flushes them
+	fn finish_callback(&mut self, callback: u32, data: u32) -> io::Result<()> {
+		self.objects.remove(&callback);
+		self.send_all(vec![
+			(callback, 0, vec![WireArgument::UnInt(data)]),
+			(1, 1, vec![WireArgument::UnInt(callback)]),
+		])
+	}
+
+	fn registries(&self) -> Vec<u32> {
+		let registries =
+			self.objects.iter().filter(|(_, kind)| **kind == WaylandObjectKind::Registry);
+		registries.map(|(id, _)| *id).collect()
+	}
+
+	fn send_global(&mut self, registry: u32, name: u32, iface: &str, ver: u32) -> io::Result<()> {
+		let args = vec![
+			WireArgument::UnInt(name),
+			WireArgument::String(Some(iface.to_string())),
+			WireArgument::UnInt(ver),
+		];
+		self.send(registry, 0, args)
+	}
+
+	fn fire_frames(&mut self, callbacks: Vec<u32>) -> io::Result<()> {
+		if callbacks.is_empty() {
+			return Ok(());
+		}
+		self.clock += 16;
+		for callback in callbacks {
+			self.finish_callback(callback, self.clock)?;
+		}
+		Ok(())
+	}
+
+	// the first commit of an xdg surface gets the initial configure. the size
+	// is left to the client
+	fn configure_xdg(&mut self, surface: u32) -> io::Result<()> {
+		let Some((xdg_surface, configured)) = self.xdg_surfaces.get_mut(&surface) else {
+			return Ok(());
+		};
+		if *configured {
+			return Ok(());
+		}
+		*configured = true;
+		let xdg_surface = *xdg_surface;
+		if let Some(toplevel) = self.toplevels.get(&xdg_surface).copied() {
+			let args = vec![WireArgument::Int(0), WireArgument::Int(0), WireArgument::Arr(vec![])];
+			self.send(toplevel, 0, args)?;
+		}
+		self.serial += 1;
+		self.send(xdg_surface, 0, vec![WireArgument::UnInt(self.serial)])
+	}
+
+	fn post_error(&mut self, object: u32, code: u32, msg: String) -> io::Result<()> {
+		let args = vec![
+			WireArgument::Obj(object),
+			WireArgument::UnInt(code),
+			WireArgument::String(Some(msg)),
+		];
+		self.send(1, 0, args)?;
+		self.closed = true;
+		self.sock.shutdown(Shutdown::Both)
+	}
+
+	fn handle(
+		&mut self,
+		object: u32,
+		opcode: u16,
+		payload: &[u8],
+		fds: &mut VecDeque<OwnedFd>,
+	) -> io::Result<()> {
+		let Some(kind) = self.objects.get(&object).copied() else {
+			// libwayland blames the display for these, the object doesn't exist
+			return self.post_error(
+				1,
+				wl_display::Error::InvalidObject as u32,
+				format!("unknown object {object}"),
+			);
+		};
+		let op = OpCode(opcode as u32);
+		let Some(signature) = kind.request_signature(op) else {
+			return self.post_error(
+				object,
+				wl_display::Error::InvalidMethod as u32,
+				format!("{kind}@{object} has no request {opcode}"),
+			);
+		};
+		let fd_count = signature.iter().filter(|k| **k == WireArgumentKind::FileDescriptor).count();
+		let taken = fds.len().min(fd_count);
+		let args = match decode_args(payload, signature, &mut fds.drain(..taken)) {
+			Ok(args) => args,
+			Err(er) => {
+				return self.post_error(
+					object,
+					wl_display::Error::InvalidMethod as u32,
+					er.to_string(),
+				);
+			}
+		};
+
+		if let Some(child) = kind.request_creates(op) {
+			let id = args.iter().find_map(|arg| match arg {
+				WireArgument::NewId(id) => Some(*id),
+				_ => None,
+			});
+			if let Some(id) = id {
+				self.objects.insert(id, child);
+			}
+		}
+
+		match (kind, opcode) {
+			// sync
+			(WaylandObjectKind::Display, 0) => {
+				if let Some(WireArgument::NewId(callback)) = args.first() {
+					let callback = *callback;
+					self.serial += 1;
+					self.finish_callback(callback, self.serial)?;
+				}
+			}
+			// get_registry
+			(WaylandObjectKind::Display, 1) => {
+				if let Some(WireArgument::NewId(registry)) = args.first() {
+					let registry = *registry;
+					for (name, iface, ver) in self.globals.clone() {
+						self.send_global(registry, name, &iface, ver)?;
+					}
+				}
+			}
+			// bind
+			(WaylandObjectKind::Registry, 0) => {
+				if let Some(WireArgument::NewIdSpecific(iface, _, id)) = args.get(1) {
+					match WaylandObjectKind::from_interface(iface) {
+						Some(kind) => {
+							self.objects.insert(*id, kind);
+							if kind == WaylandObjectKind::Shm {
+								for format in self.shm_formats.clone() {
+									self.send(*id, 0, vec![WireArgument::UnInt(format)])?;
+								}
+							}
+						}
+						None => {
+							let msg = format!("unknown interface {iface}");
+							self.post_error(object, wl_display::Error::InvalidObject as u32, msg)?;
+						}
+					}
+				}
+			}
+			// frame
+			(WaylandObjectKind::Surface, 3) => {
+				if let Some(WireArgument::NewId(callback)) = args.first() {
+					self.frames.entry(object).or_default().push(*callback);
+				}
+			}
+			// commit
+			(WaylandObjectKind::Surface, 6) => {
+				self.configure_xdg(object)?;
+				let callbacks = self.frames.remove(&object).unwrap_or_default();
+				if self.occluded {
+					self.held.extend(callbacks);
+				} else {
+					self.fire_frames(callbacks)?;
+				}
+			}
+			// get_xdg_surface
+			(WaylandObjectKind::XdgWmBase, 2) => {
+				if let [WireArgument::NewId(id), WireArgument::Obj(surface)] = args[..] {
+					self.xdg_surfaces.insert(surface, (id, false));
+				}
+			}
+			// get_toplevel
+			(WaylandObjectKind::XdgSurface, 1) => {
+				if let Some(WireArgument::NewId(toplevel)) = args.first() {
+					self.toplevels.insert(object, *toplevel);
+				}
+			}
+			_ => (),
+		}
+
+		if kind.is_destructor(op) {
+			self.objects.remove(&object);
+			self.send(1, 1, vec![WireArgument::UnInt(object)])?;
+		}
+
+		self.requests.push(RecordedRequest {
+			object,
+			interface: kind.as_str(),
+			opcode,
+			name: kind.request_name(op).unwrap_or_default(),
+			args,
+		});
+		Ok(())
+	}
+}
+
+fn serve(sock: UnixStream, shared: Arc<Mutex<Shared>>, stop: Arc<AtomicBool>) {
+	let mut buf: Vec<u8> = vec![];
+	let mut fds = VecDeque::new();
+	let mut chunk = [0; 4096];
+	while !stop.load(Ordering::Relaxed) {
+		match poll_fd(&sock, libc::POLLIN, POLL_MS) {
+			Ok(true) => (),
+			Ok(false) => continue,
+			Err(_) => return,
+		}
+		match recv_with_fds(&sock, &mut chunk, &mut fds) {
+			// client hung up
+			Ok((0, _)) => return,
+			Ok((n, _)) => buf.extend_from_slice(&chunk[..n]),
+			Err(er) if er.kind() == io::ErrorKind::WouldBlock => continue,
+			Err(_) => return,
+		}
+
+		while buf.len() >= 8 {
+			let object = u32::from_ne_bytes([buf[0], buf[1], buf[2], buf[3]]);
+			let word2 = u32::from_ne_bytes([buf[4], buf[5], buf[6], buf[7]]);
+			let len = (word2 >> 16) as usize;
+			if len < 8 {
+				return;
+			}
+			if buf.len() < len {
+				break;
+			}
+			let opcode = (word2 & 0xffff) as u16;
+			let mut shared = shared.lock().unwrap();
+			let res = shared.handle(object, opcode, &buf[8..len], &mut fds);
+			if res.is_err() || shared.closed {
+				return;
+			}
+			buf.drain(..len);
+		}
+	}
+}
+
+// fixtures for the unit tests of the crate, they need its internals
+#[cfg(test)]
+pub(crate) mod fixtures {
+	use super::MockCompositor;
+	use crate::{
+		PixelFormat, Rl, WaytinierError,
+		abstraction::app::{App, Presenter},
+		wayland::{Id, buffer::BufferBackend, shm::ShmBackend, surface::Surface},
+	};
+
+	// a presenter for apps that only talk to the globals. its surface was never
+	// created on the compositor
+	pub(crate) struct NoPresenter;
+
+	impl Presenter<ShmBackend> for NoPresenter {
+		fn backend(&self) -> ShmBackend {
+			ShmBackend::new()
+		}
+
+		fn surface(&self) -> Rl<Surface> {
+			Surface::new(Id(0), PixelFormat::Argb8888, 1)
+		}
+	}
+
+	pub(crate) type TestApp = App<ShmBackend, NoPresenter>;
+
+	// an app connected to a new mock compositor with the given globals
+	pub(crate) fn connect_app(
+		globals: &[(&str, u32)],
+	) -> Result<(MockCompositor, TestApp), WaytinierError> {
+		let (mock, client) = MockCompositor::spawn(globals)?;
+		Ok((mock, TestApp::from_stream(client)?))
+	}
+
+	// an app with wl_shm and one xrgb8888 surface the compositor knows about.
+	// the requests up to here are taken, the mock only has what comes after
+	pub(crate) fn surface_app(
+		compositor_version: u32,
+	) -> Result<(MockCompositor, TestApp, Rl<Surface>), WaytinierError> {
+		let (mock, mut app) = connect_app(&[("wl_compositor", compositor_version), ("wl_shm", 2)])?;
+		let (pending, surface) =
+			app.compositor.borrow().make_surface(&mut app.wlim, PixelFormat::Xrgb8888)?;
+		app.handle_requests(pending)?;
+		app.roundtrip()?;
+		mock.take_requests();
+		Ok((mock, app, surface))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		error::Error,
+		time::{Duration, Instant},
+	};
+
+	use super::*;
+	use crate::wayland::{
+		AppRequest, Id, IdentManager,
+		display::Display,
+		protocol::{wl_compositor, wl_registry},
+		registry::Registry,
+		wire::{MessageManager, WireEventRaw},
+	};
+
+	// reads until the callback with the given id fires. events for known
+	// objects are dispatched, the rest is handed back untouched
+	fn wait_for_callback(
+		wlmm: &mut MessageManager,
+		wlim: &mut IdentManager,
+		callback: Id,
+	) -> Result<(Vec<AppRequest>, Vec<WireEventRaw>), Box<dyn Error>> {
+		let deadline = Instant::now() + Duration::from_secs(5);
+		let mut handled = vec![];
+		let mut unknown = vec![];
+		loop {
+			wlmm.read_socket()?;
+			while let Some(event) = wlmm.next_event(wlim)? {
+				if event.recv_id == callback {
+					return Ok((handled, unknown));
+				}
+				if wlim.kind_of(event.recv_id).is_some() {
+					handled.extend(wlim.dispatch(event)?);
+				} else {
+					unknown.push(event);
+				}
+			}
+			assert!(Instant::now() < deadline, "timed out waiting for wl_callback.done");
+			thread::sleep(Duration::from_millis(1));
+		}
+	}
+
+	fn connect(
+		globals: &[(&str, u32)],
+	) -> Result<(MockCompositor, MessageManager, IdentManager, Id), Box<dyn Error>> {
+		let (mock, client) = MockCompositor::spawn(globals)?;
+		let wlmm = MessageManager::from_stream(client)?;
+		let mut wlim = IdentManager::default();
+		let display_id = Display::new_registered(&mut wlim).borrow().id;
+		Ok((mock, wlmm, wlim, display_id))
+	}
+
+	#[test]
+	fn registry_roundtrip() -> Result<(), Box<dyn Error>> {
+		let (mock, mut wlmm, mut wlim, display_id) =
+			connect(&[("wl_compositor", 6), ("wl_shm", 2)])?;
+		let registry_id = Registry::new_registered(&mut wlim).borrow().id;
+
+		wlmm.send_request(wl_display::get_registry(display_id, registry_id))?;
+		let callback = wlim.new_id();
+		wlmm.send_request(wl_display::sync(display_id, callback))?;
+		assert!(wlmm.flush()?);
+
+		let (handled, _) = wait_for_callback(&mut wlmm, &mut wlim, callback)?;
+		let globals: Vec<(u32, String, u32)> = handled
+			.into_iter()
+			.filter_map(|req| match req {
+				AppRequest::RegistryPush(name, entry) => {
+					Some((name.0, entry.interface, entry.version))
+				}
+				_ => None,
+			})
+			.collect();
+		assert_eq!(
+			globals,
+			[(1, String::from("wl_compositor"), 6), (2, String::from("wl_shm"), 2)]
+		);
+
+		let labels: Vec<String> = mock.take_requests().iter().map(|req| req.label()).collect();
+		assert_eq!(labels, ["wl_display@1.get_registry", "wl_display@1.sync"]);
+		assert_eq!(mock.object_interface(registry_id.0), Some("wl_registry"));
+		// the callback is gone again after done
+		assert_eq!(mock.object_interface(callback.0), None);
+		Ok(())
+	}
+
+	#[test]
+	fn bind_creates_object() -> Result<(), Box<dyn Error>> {
+		let (mock, mut wlmm, mut wlim, display_id) = connect(&[("wl_compositor", 6)])?;
+		let registry_id = wlim.new_id();
+		let compositor_id = wlim.new_id();
+		let surface_id = wlim.new_id();
+
+		wlmm.send_request(wl_display::get_registry(display_id, registry_id))?;
+		wlmm.send_request(wl_registry::bind(registry_id, 1, "wl_compositor", 5, compositor_id))?;
+		wlmm.send_request(wl_compositor::create_surface(compositor_id, surface_id))?;
+		let callback = wlim.new_id();
+		wlmm.send_request(wl_display::sync(display_id, callback))?;
+		wlmm.flush()?;
+		wait_for_callback(&mut wlmm, &mut wlim, callback)?;
+
+		assert_eq!(mock.object_interface(compositor_id.0), Some("wl_compositor"));
+		assert_eq!(mock.object_interface(surface_id.0), Some("wl_surface"));
+		let bind = mock.take_requests().into_iter().find(|req| req.name == "bind").unwrap();
+		assert!(matches!(
+			&bind.args[..],
+			[WireArgument::UnInt(1), WireArgument::NewIdSpecific(iface, 5, _)] if iface == "wl_compositor"
+		));
+		Ok(())
+	}
+
+	#[test]
+	fn unknown_object_is_a_protocol_error() -> Result<(), Box<dyn Error>> {
+		let (mock, mut wlmm, mut wlim, display_id) = connect(&[])?;
+
+		// a surface on a compositor that was never bound
+		wlmm.send_request(wl_compositor::create_surface(Id(7), Id(8)))?;
+		let callback = wlim.new_id();
+		wlmm.send_request(wl_display::sync(display_id, callback))?;
+		wlmm.flush()?;
+
+		// the error comes in, then the compositor hangs up without answering
+		// the sync
+		let deadline = Instant::now() + Duration::from_secs(5);
+		let mut handled = vec![];
+		let closed = loop {
+			match wlmm.read_socket() {
+				Ok(_) => (),
+				Err(er) => break er,
+			}
+			while let Some(event) = wlmm.next_event(&wlim)? {
+				handled.extend(wlim.dispatch(event)?);
+			}
+			assert!(Instant::now() < deadline, "the compositor never hung up");
+			thread::sleep(Duration::from_millis(1));
+		};
+		assert!(closed.is_disconnect(), "{closed}");
+
+		let er = handled
+			.into_iter()
+			.find_map(|req| match req {
+				AppRequest::ProtocolError(object, code, message) => {
+					Some(wlim.protocol_error(object, code, message))
+				}
+				_ => None,
+			})
+			.expect("wl_display.error should come back as an error");
+		assert_eq!(er.interface, Some("wl_display"));
+		assert_eq!(er.name, Some("invalid_object"));
+		assert!(er.message.contains("unknown object 7"), "{er}");
+		// nothing after the bad request was handled
+		assert!(mock.take_requests().is_empty());
+		Ok(())
+	}
+
+	#[test]
+	fn events_reach_the_client() -> Result<(), Box<dyn Error>> {
+		let (mock, mut wlmm, mut wlim, display_id) = connect(&[])?;
+
+		mock.send_event(42, 3, vec![WireArgument::UnInt(7), WireArgument::String(None)])?;
+		let callback = wlim.new_id();
+		wlmm.send_request(wl_display::sync(display_id, callback))?;
+		wlmm.flush()?;
+		let (_, unknown) = wait_for_callback(&mut wlmm, &mut wlim, callback)?;
+
+		assert_eq!(unknown.len(), 1);
+		assert_eq!((unknown[0].recv_id, unknown[0].opcode), (Id(42), 3));
+		assert_eq!(unknown[0].payload, [7, 0, 0, 0, 0, 0, 0, 0].map(u8::from));
+		Ok(())
+	}
+}
@@ -1,3 +1,16 @@
+use crate::{
+	Rl, WaytinierError,
+	wayland::{AppRequest, IdentManager, registry::Registry},
+};
+
+// backends only live inside the crate, they need its internals to bind
+#[allow(private_interfaces)]
 pub trait BufferBackend: Sized {
 	fn new() -> Self;
+	// binds the globals the backend needs, once the registry is known
+	fn bind(
+		&mut self,
+		wlim: &mut IdentManager,
+		registry: &Rl<Registry>,
+	) -> Result<Vec<AppRequest>, WaytinierError>;
 }
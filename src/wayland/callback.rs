@@ -0,0 +1,60 @@
+use std::cell::Cell;
+
+use crate::{
+	Rl, WaytinierError, rl,
+	wayland::{
+		AppRequest, Id, IdentManager, OpCode, WaylandObject, WaylandObjectKind,
+		protocol::wl_callback, wire::WireArgument,
+	},
+};
+
+// a one shot wl_callback, from wl_display.sync or wl_surface.frame.
+// the compositor deletes it right after done. the data is kept with the
+// object and not by id, the id gets reused after delete_id
+pub(crate) struct Callback {
+	pub(crate) id: Id,
+	pub(crate) done: Cell<Option<u32>>,
+}
+
+impl Callback {
+	pub(crate) fn new(id: Id) -> Rl<Self> {
+		rl!(Self {
+			id,
+			done: Cell::new(None),
+		})
+	}
+
+	pub(crate) fn new_registered(wlim: &mut IdentManager) -> Rl<Self> {
+		let cb = Self::new(Id(0));
+		let id = wlim.new_id_registered(cb.clone());
+		cb.borrow_mut().id = id;
+		cb
+	}
+
+	// the callback data once done came in, a serial for syncs and a
+	// timestamp in milliseconds for frames
+	pub(crate) fn done(&self) -> Option<u32> {
+		self.done.get()
+	}
+}
+
+impl WaylandObject for Callback {
+	fn handle(
+		&self,
+		opcode: OpCode,
+		args: Vec<WireArgument>,
+	) -> Result<Vec<AppRequest>, WaytinierError> {
+		match wl_callback::Event::from_args(opcode, args)? {
+			wl_callback::Event::Done {
+				callback_data,
+			} => {
+				self.done.set(Some(callback_data));
+				Ok(vec![])
+			}
+		}
+	}
+
+	fn kind(&self) -> WaylandObjectKind {
+		WaylandObjectKind::Callback
+	}
+}
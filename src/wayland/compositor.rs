@@ -1,10 +1,10 @@
-use std::{error::Error, os::fd::OwnedFd};
-
 use crate::{
-	Rl, rl,
+	Rl, WaytinierError, rl,
 	wayland::{
-		AppRequest, Id, IdentManager, OpCode, PixelFormat, Raw, Request, WaylandObject,
+		AppRequest, Id, IdentManager, OpCode, PixelFormat, Request, WaylandObject,
 		WaylandObjectKind,
+		protocol::wl_compositor,
+		region::Region,
 		registry::Registry,
 		surface::Surface,
 		wire::{WireArgument, WireRequest},
@@ -13,40 +13,43 @@ use crate::{
 
 pub(crate) struct Compositor {
 	pub(crate) id: Id,
+	pub(crate) version: u32,
 }
 
 impl Compositor {
 	pub(crate) fn new(id: Id) -> Self {
 		Self {
 			id,
+			version: 1,
 		}
 	}
 
 	pub fn new_bound(
 		wlim: &mut IdentManager,
 		registry: Rl<Registry>,
-	) -> Result<Rl<Self>, Box<dyn Error>> {
+	) -> Result<(Vec<AppRequest>, Rl<Self>), WaytinierError> {
 		let compositor = rl!(Self::new(Id(0)));
 		let id = wlim.new_id_registered(compositor.clone());
 		compositor.borrow_mut().id = id;
-		registry.borrow_mut().bind(id, WaylandObjectKind::Compositor, 5)?;
-		Ok(compositor)
+		let (pending, version) = registry.borrow_mut().bind(
+			id,
+			WaylandObjectKind::Compositor,
+			1..=wl_compositor::VERSION,
+		)?;
+		compositor.borrow_mut().version = version;
+		Ok((pending, compositor))
 	}
 
 	fn wl_create_surface(&self, id: Id) -> WireRequest {
-		WireRequest {
-			sender_id: self.id,
-			opcode: OpCode(0),
-			args: vec![WireArgument::UnInt(id.raw())],
-		}
+		wl_compositor::create_surface(self.id, id)
 	}
 
 	pub fn make_surface(
 		&self,
 		wlim: &mut IdentManager,
-	) -> Result<(Vec<AppRequest>, Rl<Surface>), Box<dyn Error>> {
-		// TODO allow choice by user
-		let surface = Surface::new(Id(0), PixelFormat::Argb888);
+		pf: PixelFormat,
+	) -> Result<(Vec<AppRequest>, Rl<Surface>), WaytinierError> {
+		let surface = Surface::new(Id(0), pf, self.version);
 		let id = wlim.new_id_registered(surface.clone());
 		surface.borrow_mut().id = id;
 
@@ -59,20 +62,37 @@ impl Compositor {
 			surface,
 		))
 	}
+
+	pub(crate) fn create_region(&self, wlim: &mut IdentManager) -> (Vec<AppRequest>, Rl<Region>) {
+		let region = Region::new_registered(wlim, self.version);
+		let id = region.borrow().id;
+		(
+			vec![AppRequest::Request(Request {
+				inner: wl_compositor::create_region(self.id, id),
+				opname: "create_region",
+				kind: self.kind_str(),
+			})],
+			region,
+		)
+	}
 }
 
 impl WaylandObject for Compositor {
 	fn handle(
 		&self,
-		_payload: &[u8],
-		_opcode: super::OpCode,
-		_fds: Vec<OwnedFd>,
-	) -> Result<Vec<AppRequest>, Box<dyn Error>> {
-		todo!()
+		opcode: OpCode,
+		args: Vec<WireArgument>,
+	) -> Result<Vec<AppRequest>, WaytinierError> {
+		// wl_compositor has no events
+		match wl_compositor::Event::from_args(opcode, args)? {}
 	}
 
 	#[inline]
 	fn kind(&self) -> WaylandObjectKind {
 		WaylandObjectKind::Compositor
 	}
+
+	fn version(&self) -> u32 {
+		self.version
+	}
 }
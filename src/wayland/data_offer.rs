@@ -0,0 +1,70 @@
+use std::cell::{Cell, RefCell};
+
+use crate::{
+	Rl, WaytinierError, rl,
+	wayland::{
+		AppRequest, DebugLevel, Id, OpCode, WaylandObject, WaylandObjectKind,
+		protocol::wl_data_offer, wire::WireArgument,
+	},
+};
+
+// created by the compositor through wl_data_device.data_offer, followed by
+// one offer event per mime type it can be read as
+pub(crate) struct DataOffer {
+	pub(crate) id: Id,
+	pub(crate) version: u32,
+	pub(crate) mime_types: RefCell<Vec<String>>,
+	pub(crate) source_actions: Cell<u32>,
+	pub(crate) action: Cell<u32>,
+}
+
+impl DataOffer {
+	pub(crate) fn new(id: Id, version: u32) -> Rl<Self> {
+		rl!(Self {
+			id,
+			version,
+			mime_types: RefCell::new(vec![]),
+			source_actions: Cell::new(0),
+			action: Cell::new(0),
+		})
+	}
+}
+
+impl WaylandObject for DataOffer {
+	fn handle(
+		&self,
+		opcode: OpCode,
+		args: Vec<WireArgument>,
+	) -> Result<Vec<AppRequest>, WaytinierError> {
+		let msg = match wl_data_offer::Event::from_args(opcode, args)? {
+			wl_data_offer::Event::Offer {
+				mime_type,
+			} => {
+				let msg = format!("offer {} can be read as {mime_type}", self.id);
+				self.mime_types.borrow_mut().push(mime_type);
+				msg
+			}
+			wl_data_offer::Event::SourceActions {
+				source_actions,
+			} => {
+				self.source_actions.set(source_actions);
+				format!("offer {} source actions: {source_actions:#x}", self.id)
+			}
+			wl_data_offer::Event::Action {
+				dnd_action,
+			} => {
+				self.action.set(dnd_action);
+				format!("offer {} action: {dnd_action:#x}", self.id)
+			}
+		};
+		Ok(vec![AppRequest::DebugMessage(DebugLevel::Trivial, msg)])
+	}
+
+	fn kind(&self) -> WaylandObjectKind {
+		WaylandObjectKind::DataOffer
+	}
+
+	fn version(&self) -> u32 {
+		self.version
+	}
+}
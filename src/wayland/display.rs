@@ -1,11 +1,8 @@
-use std::{error::Error, os::fd::OwnedFd};
-
 use crate::{
-	Rl, rl,
+	Rl, WaytinierError, rl,
 	wayland::{
-		AppRequest, Boxed, Id, IdentManager, OpCode, Raw, WaylandError, WaylandObject,
-		WaylandObjectKind,
-		wire::{FromWirePayload, RecvError},
+		AppRequest, Id, IdentManager, OpCode, Request, WaylandObject, WaylandObjectKind,
+		protocol::wl_display, wire::WireArgument,
 	},
 };
 
@@ -26,37 +23,43 @@ impl Display {
 		display.borrow_mut().id = id;
 		display
 	}
+
+	pub(crate) fn get_registry(&self, registry: Id) -> Vec<AppRequest> {
+		vec![AppRequest::Request(Request {
+			inner: wl_display::get_registry(self.id, registry),
+			opname: "get_registry",
+			kind: self.kind_str(),
+		})]
+	}
+
+	pub(crate) fn sync(&self, callback: Id) -> Vec<AppRequest> {
+		vec![AppRequest::Request(Request {
+			inner: wl_display::sync(self.id, callback),
+			opname: "sync",
+			kind: self.kind_str(),
+		})]
+	}
 }
 
 impl WaylandObject for Display {
 	fn handle(
 		&self,
-		p: &[u8],
 		opcode: OpCode,
-		_fds: Vec<OwnedFd>,
-	) -> Result<Vec<AppRequest>, Box<dyn Error>> {
+		args: Vec<WireArgument>,
+	) -> Result<Vec<AppRequest>, WaytinierError> {
 		let mut pending = vec![];
-		match opcode.raw() {
-			0 => {
-				let obj_id = u32::from_wire(p)?;
-				let code = u32::from_wire(&p[4..])?;
-				let message = String::from_wire(&p[8..])?;
-				pending.push(AppRequest::Error {
-					0: RecvError {
-						recv_id: self.id,
-						id: Id(obj_id),
-						code: OpCode(code),
-						msg: message,
-					}
-					.boxed(),
-				});
-			}
-			1 => {
-				let deleted_id = u32::from_wire(p)?;
-				pending.push(AppRequest::IdDeletion(Id(deleted_id)));
+		match wl_display::Event::from_args(opcode, args)? {
+			wl_display::Event::Error {
+				object_id,
+				code,
+				message,
+			} => {
+				pending.push(AppRequest::ProtocolError(object_id, code, message));
 			}
-			inv => {
-				return Err(WaylandError::InvalidOpCode(OpCode(inv), self.kind_str()).boxed());
+			wl_display::Event::DeleteId {
+				id,
+			} => {
+				pending.push(AppRequest::IdDeletion(Id(id)));
 			}
 		}
 		Ok(pending)
@@ -0,0 +1,312 @@
+// wl_fixed_t, a signed 24.8 fixed point number.
+// the raw value is what goes over the wire, 256 raw units make up 1.0.
+// the operators wrap around on overflow like libwayland's C code does, the
+// checked_ methods give None instead
+
+use std::{
+	fmt::{self, Display},
+	ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed(i32);
+
+impl Fixed {
+	pub const ZERO: Fixed = Fixed(0);
+	pub const ONE: Fixed = Fixed(256);
+	pub const MIN: Fixed = Fixed(i32::MIN);
+	pub const MAX: Fixed = Fixed(i32::MAX);
+	// the smallest step the type can represent, 1/256
+	pub const EPSILON: Fixed = Fixed(1);
+
+	pub const fn from_raw(raw: i32) -> Self {
+		Self(raw)
+	}
+
+	pub const fn raw(self) -> i32 {
+		self.0
+	}
+
+	// integers outside of the 24 bit range wrap around, like libwayland's
+	// wl_fixed_from_int
+	pub const fn from_int(x: i32) -> Self {
+		Self(x.wrapping_mul(256))
+	}
+
+	// rounds to the nearest representable value, saturating at MIN/MAX.
+	// NaN becomes zero
+	pub fn from_f64(x: f64) -> Self {
+		Self((x * 256.).round() as i32)
+	}
+
+	pub fn from_f32(x: f32) -> Self {
+		Self::from_f64(x as f64)
+	}
+
+	pub fn to_f64(self) -> f64 {
+		self.0 as f64 / 256.
+	}
+
+	pub fn to_f32(self) -> f32 {
+		self.to_f64() as f32
+	}
+
+	// rounds towards zero
+	pub const fn to_int(self) -> i32 {
+		self.0 / 256
+	}
+
+	// rounds towards negative infinity
+	pub const fn floor(self) -> i32 {
+		self.0 >> 8
+	}
+
+	pub const fn fract(self) -> Fixed {
+		Fixed(self.0 - self.to_int() * 256)
+	}
+
+	pub const fn checked_add(self, rhs: Fixed) -> Option<Fixed> {
+		match self.0.checked_add(rhs.0) {
+			Some(x) => Some(Fixed(x)),
+			None => None,
+		}
+	}
+
+	pub const fn checked_sub(self, rhs: Fixed) -> Option<Fixed> {
+		match self.0.checked_sub(rhs.0) {
+			Some(x) => Some(Fixed(x)),
+			None => None,
+		}
+	}
+
+	// None when the product doesn't fit, the lost fractional bits round
+	// towards negative infinity like the operator does
+	pub const fn checked_mul(self, rhs: Fixed) -> Option<Fixed> {
+		narrow((self.0 as i64 * rhs.0 as i64) >> 8)
+	}
+
+	// None for a zero divisor or a quotient that doesn't fit
+	pub const fn checked_div(self, rhs: Fixed) -> Option<Fixed> {
+		if rhs.0 == 0 {
+			return None;
+		}
+		narrow(((self.0 as i64) << 8) / rhs.0 as i64)
+	}
+}
+
+const fn narrow(raw: i64) -> Option<Fixed> {
+	if raw < i32::MIN as i64 || raw > i32::MAX as i64 {
+		None
+	} else {
+		Some(Fixed(raw as i32))
+	}
+}
+
+impl From<i32> for Fixed {
+	fn from(x: i32) -> Self {
+		Self::from_int(x)
+	}
+}
+
+impl From<f64> for Fixed {
+	fn from(x: f64) -> Self {
+		Self::from_f64(x)
+	}
+}
+
+impl From<f32> for Fixed {
+	fn from(x: f32) -> Self {
+		Self::from_f32(x)
+	}
+}
+
+impl From<Fixed> for f64 {
+	fn from(x: Fixed) -> Self {
+		x.to_f64()
+	}
+}
+
+impl From<Fixed> for f32 {
+	fn from(x: Fixed) -> Self {
+		x.to_f32()
+	}
+}
+
+impl Display for Fixed {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		Display::fmt(&self.to_f64(), f)
+	}
+}
+
+// wraps around on overflow
+impl Add for Fixed {
+	type Output = Fixed;
+	fn add(self, rhs: Fixed) -> Fixed {
+		Fixed(self.0.wrapping_add(rhs.0))
+	}
+}
+
+// wraps around on overflow
+impl Sub for Fixed {
+	type Output = Fixed;
+	fn sub(self, rhs: Fixed) -> Fixed {
+		Fixed(self.0.wrapping_sub(rhs.0))
+	}
+}
+
+// -MIN wraps back to MIN
+impl Neg for Fixed {
+	type Output = Fixed;
+	fn neg(self) -> Fixed {
+		Fixed(self.0.wrapping_neg())
+	}
+}
+
+// the product has 16 fractional bits, shift 8 of them back out. that rounds
+// towards negative infinity, and a product that doesn't fit in 24.8 is
+// truncated to its low 32 bits
+impl Mul for Fixed {
+	type Output = Fixed;
+	fn mul(self, rhs: Fixed) -> Fixed {
+		Fixed(((self.0 as i64 * rhs.0 as i64) >> 8) as i32)
+	}
+}
+
+// panics on division by zero like the integer types do. the quotient rounds
+// towards zero, one that doesn't fit in 24.8 is truncated to its low 32 bits
+impl Div for Fixed {
+	type Output = Fixed;
+	fn div(self, rhs: Fixed) -> Fixed {
+		Fixed((((self.0 as i64) << 8) / rhs.0 as i64) as i32)
+	}
+}
+
+// wraps around on overflow
+impl Mul<i32> for Fixed {
+	type Output = Fixed;
+	fn mul(self, rhs: i32) -> Fixed {
+		Fixed(self.0.wrapping_mul(rhs))
+	}
+}
+
+// panics on division by zero, rounds towards zero. MIN / -1 wraps to MIN
+impl Div<i32> for Fixed {
+	type Output = Fixed;
+	fn div(self, rhs: i32) -> Fixed {
+		Fixed(self.0.wrapping_div(rhs))
+	}
+}
+
+impl AddAssign for Fixed {
+	fn add_assign(&mut self, rhs: Fixed) {
+		*self = *self + rhs;
+	}
+}
+
+impl SubAssign for Fixed {
+	fn sub_assign(&mut self, rhs: Fixed) {
+		*self = *self - rhs;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trips() {
+		for x in [0, 1, -1, 42, -42, 8_388_607, -8_388_608] {
+			assert_eq!(Fixed::from_int(x).to_int(), x);
+			assert_eq!(Fixed::from_int(x).raw(), x * 256);
+		}
+		for x in [0., 0.5, -0.5, 1.25, -1.25, 1000.00390625, -8_388_608.] {
+			assert_eq!(Fixed::from_f64(x).to_f64(), x);
+		}
+		assert_eq!(Fixed::from_raw(-3).raw(), -3);
+		assert_eq!(Fixed::from_f32(2.5).to_f32(), 2.5);
+		assert_eq!(Fixed::from(-7).to_string(), "-7");
+		assert_eq!(Fixed::from(0.75).to_string(), "0.75");
+	}
+
+	#[test]
+	fn rounding() {
+		// from floats to the nearest 1/256, halves away from zero
+		assert_eq!(Fixed::from_f64(0.001).raw(), 0);
+		assert_eq!(Fixed::from_f64(0.003).raw(), 1);
+		assert_eq!(Fixed::from_f64(1.5 / 256.).raw(), 2);
+		assert_eq!(Fixed::from_f64(-1.5 / 256.).raw(), -2);
+
+		let x = Fixed::from_f64(-1.75);
+		assert_eq!(x.to_int(), -1);
+		assert_eq!(x.floor(), -2);
+		assert_eq!(x.fract(), Fixed::from_f64(-0.75));
+		let x = Fixed::from_f64(1.75);
+		assert_eq!(x.to_int(), 1);
+		assert_eq!(x.floor(), 1);
+		assert_eq!(x.fract(), Fixed::from_f64(0.75));
+
+		// the product of two epsilons is below the last bit
+		assert_eq!(Fixed::EPSILON * Fixed::EPSILON, Fixed::ZERO);
+		assert_eq!(-Fixed::EPSILON * Fixed::EPSILON, -Fixed::EPSILON);
+		assert_eq!(Fixed::ONE / Fixed::from_int(3), Fixed::from_raw(85));
+		assert_eq!(-Fixed::ONE / Fixed::from_int(3), Fixed::from_raw(-85));
+		assert_eq!(Fixed::from_raw(-5) / 2, Fixed::from_raw(-2));
+	}
+
+	#[test]
+	fn arithmetic() {
+		let a = Fixed::from_f64(2.5);
+		let b = Fixed::from_f64(-0.75);
+		assert_eq!(a + b, Fixed::from_f64(1.75));
+		assert_eq!(a - b, Fixed::from_f64(3.25));
+		assert_eq!(a * b, Fixed::from_f64(-1.875));
+		assert_eq!(a / b, Fixed::from_raw(-853));
+		assert_eq!(a * 3, Fixed::from_f64(7.5));
+		assert_eq!(a / 2, Fixed::from_f64(1.25));
+		let mut c = a;
+		c += b;
+		c -= Fixed::ONE;
+		assert_eq!(c, Fixed::from_f64(0.75));
+	}
+
+	#[test]
+	fn overflow() {
+		// the float conversion saturates
+		assert_eq!(Fixed::from_f64(1e12), Fixed::MAX);
+		assert_eq!(Fixed::from_f64(-1e12), Fixed::MIN);
+		assert_eq!(Fixed::from_f64(f64::NAN), Fixed::ZERO);
+		// everything else wraps
+		assert_eq!(Fixed::from_int(8_388_608), Fixed::MIN);
+		assert_eq!(Fixed::MAX + Fixed::EPSILON, Fixed::MIN);
+		assert_eq!(Fixed::MIN - Fixed::EPSILON, Fixed::MAX);
+		assert_eq!(-Fixed::MIN, Fixed::MIN);
+		assert_eq!(Fixed::MAX * 2, Fixed::from_raw(-2));
+		assert_eq!(Fixed::MIN / -1, Fixed::MIN);
+		let big = Fixed::from_int(65536);
+		assert_eq!(big * big, Fixed::ZERO);
+		assert_eq!(big / Fixed::EPSILON, Fixed::ZERO);
+
+		assert_eq!(Fixed::MAX.checked_add(Fixed::EPSILON), None);
+		assert_eq!(Fixed::MIN.checked_sub(Fixed::EPSILON), None);
+		assert_eq!(big.checked_mul(big), None);
+		assert_eq!(big.checked_div(Fixed::EPSILON), None);
+		assert_eq!(Fixed::ONE.checked_div(Fixed::ZERO), None);
+		let a = Fixed::from_f64(-2.5);
+		assert_eq!(a.checked_add(Fixed::ONE), Some(a + Fixed::ONE));
+		assert_eq!(a.checked_sub(Fixed::ONE), Some(a - Fixed::ONE));
+		assert_eq!(a.checked_mul(a), Some(a * a));
+		assert_eq!(a.checked_div(Fixed::from_int(4)), Some(a / Fixed::from_int(4)));
+	}
+
+	#[test]
+	#[should_panic]
+	fn division_by_zero_panics() {
+		let _ = Fixed::ONE / Fixed::ZERO;
+	}
+
+	#[test]
+	#[should_panic]
+	fn integer_division_by_zero_panics() {
+		let _ = Fixed::ONE / 0;
+	}
+}
@@ -0,0 +1,175 @@
+// pixel layouts for shm buffers. names follow drm_fourcc.h, so the
+// components are listed from the most to the least significant bits of a
+// little endian pixel: Argb8888 is stored as b, g, r, a in memory
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum PixelFormat {
+	Argb8888,
+	Xrgb8888,
+	Abgr8888,
+	Xbgr8888,
+	Rgba8888,
+	Rgbx8888,
+	Bgra8888,
+	Bgrx8888,
+	Rgb888,
+	Bgr888,
+	Rgb565,
+	Bgr565,
+	Argb1555,
+	Xrgb1555,
+	Argb4444,
+	Xrgb4444,
+	Argb2101010,
+	Xrgb2101010,
+	Abgr2101010,
+	Xbgr2101010,
+	Abgr16161616,
+	Xbgr16161616,
+	Abgr16161616f,
+	Xbgr16161616f,
+}
+
+const fn fourcc(code: &[u8; 4]) -> u32 {
+	u32::from_le_bytes(*code)
+}
+
+impl PixelFormat {
+	pub const ALL: [PixelFormat; 24] = [
+		PixelFormat::Argb8888,
+		PixelFormat::Xrgb8888,
+		PixelFormat::Abgr8888,
+		PixelFormat::Xbgr8888,
+		PixelFormat::Rgba8888,
+		PixelFormat::Rgbx8888,
+		PixelFormat::Bgra8888,
+		PixelFormat::Bgrx8888,
+		PixelFormat::Rgb888,
+		PixelFormat::Bgr888,
+		PixelFormat::Rgb565,
+		PixelFormat::Bgr565,
+		PixelFormat::Argb1555,
+		PixelFormat::Xrgb1555,
+		PixelFormat::Argb4444,
+		PixelFormat::Xrgb4444,
+		PixelFormat::Argb2101010,
+		PixelFormat::Xrgb2101010,
+		PixelFormat::Abgr2101010,
+		PixelFormat::Xbgr2101010,
+		PixelFormat::Abgr16161616,
+		PixelFormat::Xbgr16161616,
+		PixelFormat::Abgr16161616f,
+		PixelFormat::Xbgr16161616f,
+	];
+
+	// the drm fourcc code
+	pub const fn fourcc(&self) -> u32 {
+		match self {
+			PixelFormat::Argb8888 => fourcc(b"AR24"),
+			PixelFormat::Xrgb8888 => fourcc(b"XR24"),
+			PixelFormat::Abgr8888 => fourcc(b"AB24"),
+			PixelFormat::Xbgr8888 => fourcc(b"XB24"),
+			PixelFormat::Rgba8888 => fourcc(b"RA24"),
+			PixelFormat::Rgbx8888 => fourcc(b"RX24"),
+			PixelFormat::Bgra8888 => fourcc(b"BA24"),
+			PixelFormat::Bgrx8888 => fourcc(b"BX24"),
+			PixelFormat::Rgb888 => fourcc(b"RG24"),
+			PixelFormat::Bgr888 => fourcc(b"BG24"),
+			PixelFormat::Rgb565 => fourcc(b"RG16"),
+			PixelFormat::Bgr565 => fourcc(b"BG16"),
+			PixelFormat::Argb1555 => fourcc(b"AR15"),
+			PixelFormat::Xrgb1555 => fourcc(b"XR15"),
+			PixelFormat::Argb4444 => fourcc(b"AR12"),
+			PixelFormat::Xrgb4444 => fourcc(b"XR12"),
+			PixelFormat::Argb2101010 => fourcc(b"AR30"),
+			PixelFormat::Xrgb2101010 => fourcc(b"XR30"),
+			PixelFormat::Abgr2101010 => fourcc(b"AB30"),
+			PixelFormat::Xbgr2101010 => fourcc(b"XB30"),
+			PixelFormat::Abgr16161616 => fourcc(b"AB48"),
+			PixelFormat::Xbgr16161616 => fourcc(b"XB48"),
+			PixelFormat::Abgr16161616f => fourcc(b"AB4H"),
+			PixelFormat::Xbgr16161616f => fourcc(b"XB4H"),
+		}
+	}
+
+	// the code wl_shm uses. it's the fourcc, except for the two formats
+	// every compositor has to support, those got 0 and 1
+	pub const fn wl_shm_format(&self) -> u32 {
+		match self {
+			PixelFormat::Argb8888 => 0,
+			PixelFormat::Xrgb8888 => 1,
+			other => other.fourcc(),
+		}
+	}
+
+	pub fn from_wl_shm_format(code: u32) -> Option<Self> {
+		Self::ALL.into_iter().find(|pf| pf.wl_shm_format() == code)
+	}
+
+	pub const fn bytes_per_pixel(&self) -> u32 {
+		match self {
+			PixelFormat::Rgb565
+			| PixelFormat::Bgr565
+			| PixelFormat::Argb1555
+			| PixelFormat::Xrgb1555
+			| PixelFormat::Argb4444
+			| PixelFormat::Xrgb4444 => 2,
+			PixelFormat::Rgb888 | PixelFormat::Bgr888 => 3,
+			PixelFormat::Abgr16161616
+			| PixelFormat::Xbgr16161616
+			| PixelFormat::Abgr16161616f
+			| PixelFormat::Xbgr16161616f => 8,
+			_ => 4,
+		}
+	}
+
+	// bytes in one row of a buffer width pixels wide, none if that doesn't
+	// fit in an i32
+	pub const fn stride(&self, width: i32) -> Option<i32> {
+		width.checked_mul(self.bytes_per_pixel() as i32)
+	}
+
+	pub const fn has_alpha(&self) -> bool {
+		matches!(
+			self,
+			PixelFormat::Argb8888
+				| PixelFormat::Abgr8888
+				| PixelFormat::Rgba8888
+				| PixelFormat::Bgra8888
+				| PixelFormat::Argb1555
+				| PixelFormat::Argb4444
+				| PixelFormat::Argb2101010
+				| PixelFormat::Abgr2101010
+				| PixelFormat::Abgr16161616
+				| PixelFormat::Abgr16161616f
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::wayland::protocol::wl_shm;
+
+	#[test]
+	fn codes_match_wl_shm() {
+		// the generated enum has the same values as drm_fourcc.h
+		let pairs = [
+			(PixelFormat::Argb8888, wl_shm::Format::Argb8888),
+			(PixelFormat::Xrgb8888, wl_shm::Format::Xrgb8888),
+			(PixelFormat::Rgb565, wl_shm::Format::Rgb565),
+			(PixelFormat::Xbgr8888, wl_shm::Format::Xbgr8888),
+			(PixelFormat::Abgr8888, wl_shm::Format::Abgr8888),
+			(PixelFormat::Xrgb2101010, wl_shm::Format::Xrgb2101010),
+			(PixelFormat::Abgr16161616f, wl_shm::Format::Abgr16161616f),
+		];
+		for (pf, code) in pairs {
+			assert_eq!(pf.wl_shm_format(), code as u32, "{pf:?}");
+			assert_eq!(PixelFormat::from_wl_shm_format(code as u32), Some(pf));
+		}
+		for pf in PixelFormat::ALL {
+			assert!(wl_shm::Format::from_raw(pf.wl_shm_format()).is_some(), "{pf:?}");
+		}
+		assert_eq!(PixelFormat::Argb8888.fourcc(), 0x34325241);
+	}
+}
@@ -1,44 +1,57 @@
-use std::{
-	collections::{HashMap, VecDeque},
-	error::Error,
-	fmt::Display,
-	os::fd::OwnedFd,
-};
+use std::{cmp::Ordering, collections::VecDeque, error::Error, fmt::Display, path::PathBuf};
 
 use crate::{
-	CYAN, DebugLevel, NONE, Rl, YELLOW,
+	CYAN, DebugLevel, NONE, Rl, WaytinierError, YELLOW,
 	wayland::{
+		data_offer::DataOffer,
 		registry::{RegistryEntry, RegistryName},
-		wire::WireRequest,
+		wire::{WireArgument, WireEventRaw, WireRequest, decode_args},
 	},
 	wlog,
 };
 
 pub(crate) mod buffer;
+pub(crate) mod callback;
 pub(crate) mod compositor;
+pub(crate) mod data_offer;
 pub(crate) mod display;
+pub(crate) mod fixed;
+pub(crate) mod format;
+pub(crate) mod protocol;
+pub(crate) mod region;
 pub(crate) mod registry;
 pub(crate) mod shm;
 pub(crate) mod surface;
+pub(crate) mod swapchain;
+pub(crate) mod trace;
 pub(crate) mod wire;
+pub(crate) mod xdg_shell;
+
+pub use fixed::Fixed;
+pub use format::PixelFormat;
+pub use region::SurfaceRegion;
+pub use surface::Transform;
+pub use wire::WireArgumentKind;
 
-struct Request {
-	inner: WireRequest,
-	opname: &'static str,
-	kind: &'static str,
+pub(crate) struct Request {
+	pub(crate) inner: WireRequest,
+	pub(crate) opname: &'static str,
+	pub(crate) kind: &'static str,
 }
 
 pub(crate) enum AppRequest {
 	Request(Request),
-	Error(Box<dyn Error>),
+	// wl_display.error with the offending object, code and message
+	ProtocolError(Id, u32, String),
 	IdDeletion(Id),
 	DebugMessage(DebugLevel, String),
 	RegistryPush(RegistryName, RegistryEntry),
+	RegistryRemove(RegistryName),
 }
 
 #[derive(Clone, Copy, Debug)]
 pub(crate) struct OpCode(pub(crate) u32);
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub(crate) struct Id(pub(crate) u32);
 
 impl Display for Id {
@@ -72,25 +85,72 @@ impl Raw for OpCode {
 pub(crate) trait WaylandObject {
 	fn handle(
 		&self,
-		payload: &[u8],
 		opcode: OpCode,
-		_fds: Vec<OwnedFd>,
-	) -> Result<Vec<AppRequest>, Box<dyn Error>>;
+		args: Vec<WireArgument>,
+	) -> Result<Vec<AppRequest>, WaytinierError>;
 	fn kind(&self) -> WaylandObjectKind;
 	fn kind_str(&self) -> &'static str {
 		self.kind().as_str()
 	}
+	// the negotiated version. globals get it at bind time, everything else
+	// inherits it from the object that created it
+	fn version(&self) -> u32 {
+		1
+	}
 }
 
 #[derive(Debug)]
-pub(crate) enum WaylandError {
-	EmptyFromWirePayload,
+pub enum WaylandError {
 	RecvLenBad,
-	NoWaylandDisplay,
-	InvalidOpCode(OpCode, &'static str),
+	NoWaylandDisplay(PathBuf),
+	NoRuntimeDir,
+	BadWaylandSocket(String),
+	InvalidOpCode(u32, &'static str),
 	ObjectNonExistent,
 	IdMapRemovalFail,
-	NotInRegistry(WaylandObjectKind),
+	NotInRegistry(&'static str),
+	FdExpected,
+	StringNotTerminated,
+	MissingArgument,
+	// expected, got
+	ArgumentMismatch(WireArgumentKind, WireArgumentKind),
+	UnexpectedNull,
+	ConnectionClosed,
+	AncillaryTruncated,
+	BadServerId(u32),
+	VersionTooOld(&'static str, u32, u32),
+	RequestTooNew(&'static str, &'static str, u32, u32),
+	BadShmSize(usize),
+	// width, height
+	BadBufferSize(i32, i32),
+	BufferOutsidePool(usize, usize, usize),
+	UnknownPresenter(usize),
+	MessageTooLong(usize),
+}
+
+// a fatal error the compositor sent through wl_display.error. interface is
+// the one of the offending object and name is the code translated through
+// that interface's error enum, when waytinier knows them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolError {
+	pub object: u32,
+	pub interface: Option<&'static str>,
+	pub code: u32,
+	pub name: Option<&'static str>,
+	pub message: String,
+}
+
+impl Error for ProtocolError {}
+
+impl Display for ProtocolError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let interface = self.interface.unwrap_or("[unknown]");
+		match self.name {
+			Some(name) => write!(f, "{interface}@{}: error {interface}.{name}", self.object)?,
+			None => write!(f, "{interface}@{}: error {}", self.object, self.code)?,
+		}
+		write!(f, ": {}", self.message)
+	}
 }
 
 impl Error for WaylandError {}
@@ -98,10 +158,15 @@ impl Error for WaylandError {}
 impl Display for WaylandError {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		match self {
-			WaylandError::EmptyFromWirePayload => write!(f, "payload from wire was empty"),
 			WaylandError::RecvLenBad => write!(f, "received len of payload was bad"),
-			WaylandError::NoWaylandDisplay => {
-				write!(f, "provided wayland display identifier doesn't exist")
+			WaylandError::NoWaylandDisplay(path) => {
+				write!(f, "no wayland display socket at {}", path.display())
+			}
+			WaylandError::NoRuntimeDir => {
+				write!(f, "XDG_RUNTIME_DIR is not set and the display name is not an absolute path")
+			}
+			WaylandError::BadWaylandSocket(var) => {
+				write!(f, "WAYLAND_SOCKET={var:?} is not an open file descriptor")
 			}
 			WaylandError::InvalidOpCode(code, name) => {
 				write!(f, "invalid opcode {code} encountered for {name}")
@@ -112,31 +177,56 @@ impl Display for WaylandError {
 			WaylandError::IdMapRemovalFail => {
 				write!(f, "failed to remove object from idmap")
 			}
-			WaylandError::NotInRegistry(kind) => {
-				write!(f, "object of kind {kind} not found in registry")
+			WaylandError::NotInRegistry(interface) => {
+				write!(f, "{interface} not found in registry")
+			}
+			WaylandError::FdExpected => {
+				write!(f, "event expected a file descriptor but none was received")
+			}
+			WaylandError::StringNotTerminated => {
+				write!(f, "string from wire was not nul terminated")
+			}
+			WaylandError::MissingArgument => write!(f, "event has fewer arguments than expected"),
+			WaylandError::ArgumentMismatch(expected, got) => {
+				write!(f, "expected an argument of type {expected:?}, got {got:?}")
+			}
+			WaylandError::UnexpectedNull => {
+				write!(f, "received null for an argument that can't be null")
+			}
+			WaylandError::ConnectionClosed => write!(f, "the compositor closed the connection"),
+			WaylandError::VersionTooOld(kind, advertised, min) => {
+				write!(f, "compositor offers {kind} version {advertised}, at least {min} is needed")
+			}
+			WaylandError::RequestTooNew(kind, name, since, version) => {
+				write!(
+					f,
+					"{kind}.{name} needs version {since} but the object has version {version}"
+				)
+			}
+			WaylandError::BadShmSize(size) => {
+				write!(f, "shm pool size {size} doesn't fit the protocol")
+			}
+			WaylandError::BadBufferSize(width, height) => {
+				write!(f, "a {width}x{height} buffer has no valid size")
+			}
+			WaylandError::BufferOutsidePool(offset, len, size) => {
+				write!(f, "buffer of {len} bytes at offset {offset} doesn't fit a pool of {size}")
+			}
+			WaylandError::UnknownPresenter(id) => write!(f, "no presenter with id {id}"),
+			WaylandError::MessageTooLong(len) => {
+				write!(f, "request of {len} bytes is longer than the wire allows")
+			}
+			WaylandError::BadServerId(id) => {
+				write!(f, "compositor created an object with unexpected id {id}")
+			}
+			WaylandError::AncillaryTruncated => {
+				write!(f, "received more file descriptors than fit in the ancillary buffer")
 			}
 		}
 	}
 }
 
-impl Boxed for WaylandError {}
-
-#[derive(Debug, Clone, Copy)]
-pub(crate) enum WaylandObjectKind {
-	Display,
-	Registry,
-	Compositor,
-	Surface,
-	Buffer,
-	XdgWmBase,
-	XdgTopLevel,
-	XdgSurface,
-	DmaBuf,
-	SharedMemory,
-	SharedMemoryPool,
-	DmaFeedback,
-	Callback,
-}
+pub(crate) use protocol::WaylandObjectKind;
 
 impl Display for WaylandObjectKind {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -144,40 +234,78 @@ impl Display for WaylandObjectKind {
 	}
 }
 
-impl WaylandObjectKind {
-	pub(crate) fn as_str(&self) -> &'static str {
-		match self {
-			WaylandObjectKind::Display => "wl_display",
-			WaylandObjectKind::Registry => "wl_registry",
-			WaylandObjectKind::Compositor => "wl_compositor",
-			WaylandObjectKind::Surface => "wl_surface",
-			WaylandObjectKind::Buffer => "wl_buffer",
-			WaylandObjectKind::XdgWmBase => "xdg_wm_base",
-			WaylandObjectKind::XdgTopLevel => "xdg_toplevel",
-			WaylandObjectKind::XdgSurface => "xdg_surface",
-			WaylandObjectKind::DmaBuf => "zwp_linux_dmabuf_v1",
-			WaylandObjectKind::SharedMemory => "wl_shm",
-			WaylandObjectKind::SharedMemoryPool => "wl_shm_poll",
-			WaylandObjectKind::DmaFeedback => "zwp_linux_dmabuf_feedback_v1",
-			WaylandObjectKind::Callback => "wl_callback",
-		}
+pub(crate) type Wlto = Rl<dyn WaylandObject>;
+
+// handlers for objects the compositor creates
+fn server_object(kind: WaylandObjectKind, id: Id, version: u32) -> Option<Wlto> {
+	match kind {
+		WaylandObjectKind::DataOffer => Some(DataOffer::new(id, version)),
+		_ => None,
 	}
 }
 
-pub(crate) type Wlto = Rl<dyn WaylandObject>;
+pub(crate) enum ObjectEntry {
+	Live(Wlto),
+	// destroyed on our side but the compositor hasn't confirmed with
+	// delete_id yet. events still in flight are decoded and thrown away,
+	// which also closes the fds they carry
+	Zombie(WaylandObjectKind),
+}
+
+// ids the compositor allocates start here, everything below is ours
+pub(crate) const SERVER_ID_START: u32 = 0xff000000;
 
-#[derive(Default)]
+// ids go live -> zombie -> free. an id only becomes free once the compositor
+// sent delete_id for it, only then is it safe to hand out again.
+// both id ranges are dense, so objects live in vecs indexed by id
 pub(crate) struct IdentManager {
-	pub(crate) idmap: HashMap<usize, Wlto>,
+	// indexed by id, slot 0 is the null object and stays empty
+	pub(crate) client: Vec<Option<ObjectEntry>>,
+	// indexed by id - SERVER_ID_START
+	pub(crate) server: Vec<Option<ObjectEntry>>,
 	pub(crate) free: VecDeque<Id>,
-	pub(crate) top_id: usize,
+}
+
+impl Default for IdentManager {
+	fn default() -> Self {
+		Self {
+			client: vec![None],
+			server: vec![],
+			free: VecDeque::new(),
+		}
+	}
 }
 
 impl IdentManager {
+	// reserves the next never used id, without an object behind it
 	pub(crate) fn new_id(&mut self) -> Id {
-		self.top_id += 1;
-		wlog!(DebugLevel::Trivial, "wlim", format!("new id picked: {}", self.top_id), YELLOW, NONE);
-		Id(self.top_id as u32)
+		self.client.push(None);
+		let id = self.client.len() - 1;
+		wlog!(DebugLevel::Trivial, "wlim", format!("new id picked: {id}"), YELLOW, NONE);
+		Id(id as u32)
+	}
+
+	fn slot(&self, id: Id) -> Option<&Option<ObjectEntry>> {
+		match id.raw().checked_sub(SERVER_ID_START) {
+			Some(ix) => self.server.get(ix as usize),
+			None => self.client.get(id.raw() as usize),
+		}
+	}
+
+	fn slot_mut(&mut self, id: Id) -> Option<&mut Option<ObjectEntry>> {
+		match id.raw().checked_sub(SERVER_ID_START) {
+			Some(ix) => self.server.get_mut(ix as usize),
+			None => self.client.get_mut(id.raw() as usize),
+		}
+	}
+
+	fn entry(&self, id: Id) -> Option<&ObjectEntry> {
+		self.slot(id)?.as_ref()
+	}
+
+	// how many objects are alive or waiting for delete_id
+	pub(crate) fn len(&self) -> usize {
+		self.client.iter().chain(&self.server).filter(|slot| slot.is_some()).count()
 	}
 
 	pub(crate) fn new_id_registered(&mut self, obj: Wlto) -> Id {
@@ -192,7 +320,7 @@ impl IdentManager {
 			wlog!(
 				DebugLevel::Trivial,
 				"wlim",
-				format!("new id picked from free pool: {}", self.top_id),
+				format!("new id picked from free pool: {id}"),
 				YELLOW,
 				NONE
 			);
@@ -200,17 +328,39 @@ impl IdentManager {
 		} else {
 			self.new_id()
 		};
-		self.idmap.insert(id.raw() as usize, obj);
+		self.client[id.raw() as usize] = Some(ObjectEntry::Live(obj));
 		id
 	}
 
-	pub(crate) fn free_id(&mut self, id: Id) -> Result<(), Box<dyn Error>> {
-		let registered =
-			self.idmap.iter().find(|(k, _)| **k == id.raw() as usize).map(|(k, _)| k).copied();
-		if let Some(r) = registered {
-			self.idmap.remove(&r).ok_or(WaylandError::IdMapRemovalFail.boxed())?;
+	// the client sent a destructor request for the object
+	pub(crate) fn destroy(&mut self, id: Id) {
+		// server ids get no delete_id, their zombies stay until the
+		// compositor hands the id out again
+		if let Some(Some(entry)) = self.slot_mut(id)
+			&& let ObjectEntry::Live(obj) = entry
+		{
+			let kind = obj.borrow().kind();
+			*entry = ObjectEntry::Zombie(kind);
+			wlog!(
+				DebugLevel::Trivial,
+				"wlim",
+				format!("{kind}@{id} is a zombie now"),
+				YELLOW,
+				NONE
+			);
+		}
+	}
+
+	// wl_display.delete_id, the id can be reused from now on
+	pub(crate) fn delete_id(&mut self, id: Id) -> Result<(), WaytinierError> {
+		// never hand out an id twice because of a stray delete_id
+		if self.slot_mut(id).and_then(Option::take).is_none() {
+			return Err(WaylandError::IdMapRemovalFail.into());
+		}
+		// server ids are the server's business to reuse
+		if id.raw() < SERVER_ID_START {
+			self.free.push_back(id);
 		}
-		self.free.push_back(id);
 		wlog!(
 			DebugLevel::Trivial,
 			"wlim",
@@ -221,20 +371,123 @@ impl IdentManager {
 		Ok(())
 	}
 
-	// ugh
+	// the kind of a live or zombie object
+	pub(crate) fn kind_of(&self, id: Id) -> Option<WaylandObjectKind> {
+		match self.entry(id)? {
+			ObjectEntry::Live(obj) => Some(obj.borrow().kind()),
+			ObjectEntry::Zombie(kind) => Some(*kind),
+		}
+	}
+
+	// objects the compositor creates through a new_id in an event. the ids
+	// have to come in order, like libwayland expects. a zombie's id can be
+	// handed out again, the compositor is done with it
+	pub(crate) fn insert_server(&mut self, id: Id, entry: ObjectEntry) -> Result<(), WaylandError> {
+		let ix = id.raw().checked_sub(SERVER_ID_START).ok_or(WaylandError::BadServerId(id.raw()))?
+			as usize;
+		match ix.cmp(&self.server.len()) {
+			Ordering::Less if !matches!(self.server[ix], Some(ObjectEntry::Live(_))) => {
+				self.server[ix] = Some(entry)
+			}
+			Ordering::Equal => self.server.push(Some(entry)),
+			_ => return Err(WaylandError::BadServerId(id.raw())),
+		}
+		Ok(())
+	}
+
+	// decodes the event with the signature of the receiving object and hands
+	// it over to its handler. objects the event creates are registered first
+	pub(crate) fn dispatch(
+		&mut self,
+		event: WireEventRaw,
+	) -> Result<Vec<AppRequest>, WaytinierError> {
+		let kind = self.kind_of(event.recv_id).ok_or(WaylandError::ObjectNonExistent)?;
+		let opcode = OpCode(event.opcode as u32);
+		let signature = kind
+			.event_signature(opcode)
+			.ok_or(WaylandError::InvalidOpCode(opcode.raw(), kind.as_str()))?;
+		let args = decode_args(&event.payload, signature, &mut event.fds.into_iter())?;
+
+		if let Some(child) = kind.event_creates(opcode) {
+			let version =
+				self.find_obj_by_id(event.recv_id).map_or(1, |obj| obj.borrow().version());
+			for arg in &args {
+				if let WireArgument::NewId(id) = arg {
+					let id = Id(*id);
+					let entry = match server_object(child, id, version) {
+						Some(obj) => ObjectEntry::Live(obj),
+						// nothing handles these yet, swallow their events
+						None => ObjectEntry::Zombie(child),
+					};
+					self.insert_server(id, entry)?;
+				}
+			}
+		}
+
+		if trace::enabled() {
+			let discarded = !matches!(self.entry(event.recv_id), Some(ObjectEntry::Live(_)));
+			trace::event(event.recv_id, kind, opcode, &args, discarded, self);
+		}
+		wlog!(
+			DebugLevel::Verbose,
+			"wlim",
+			format!(
+				"event {kind}@{}.{} with args {args:?}",
+				event.recv_id,
+				kind.event_name(opcode).unwrap_or_default()
+			),
+			YELLOW,
+			NONE
+		);
+		match self.entry(event.recv_id) {
+			Some(ObjectEntry::Live(obj)) => obj.borrow().handle(opcode, args),
+			// dropping the args closes any fds
+			_ => Ok(vec![]),
+		}
+	}
+
+	pub(crate) fn protocol_error(&self, object: Id, code: u32, message: String) -> ProtocolError {
+		let kind = self.kind_of(object);
+		ProtocolError {
+			object: object.0,
+			interface: kind.map(|kind| kind.as_str()),
+			code,
+			name: kind.and_then(|kind| kind.error_name(code)),
+			message,
+		}
+	}
+
+	// the kind of the sender, as long as it's alive and new enough for the
+	// request
+	pub(crate) fn check_request(
+		&self,
+		req: &WireRequest,
+	) -> Result<WaylandObjectKind, WaylandError> {
+		let obj = self.find_obj_by_id(req.sender_id)?.borrow();
+		let (kind, version) = (obj.kind(), obj.version());
+		let since = kind
+			.request_since(req.opcode)
+			.ok_or(WaylandError::InvalidOpCode(req.opcode.raw(), kind.as_str()))?;
+		if since > version {
+			let name = kind.request_name(req.opcode).unwrap_or_default();
+			return Err(WaylandError::RequestTooNew(kind.as_str(), name, since, version));
+		}
+		Ok(kind)
+	}
+
 	pub(crate) fn find_obj_by_id(&self, id: Id) -> Result<&Wlto, WaylandError> {
-		self.idmap
-			.iter()
-			.find(|(k, _)| **k == id.raw() as usize)
-			.map(|(_, v)| v)
-			.ok_or(WaylandError::ObjectNonExistent)
+		match self.entry(id) {
+			Some(ObjectEntry::Live(obj)) => Ok(obj),
+			_ => Err(WaylandError::ObjectNonExistent),
+		}
 	}
 }
 
 impl Drop for IdentManager {
 	fn drop(&mut self) {
-		let len = self.idmap.len();
-		self.idmap.clear();
+		let len = self.len();
+		self.client.clear();
+		self.server.clear();
 		wlog!(
 			DebugLevel::Important,
 			"wlim",
@@ -245,15 +498,161 @@ impl Drop for IdentManager {
 	}
 }
 
-pub(crate) trait Boxed: Sized {
-	fn boxed(self) -> Box<Self> {
-		Box::new(self)
+#[cfg(test)]
+mod tests {
+	use std::{
+		os::fd::{AsRawFd, FromRawFd, OwnedFd},
+		time::{Duration, Instant},
+	};
+
+	use super::*;
+	use crate::{rl, testing::MockCompositor, wayland::wire::MessageManager};
+
+	// stands in for any object, events must never reach it once it's a zombie
+	struct Dummy(WaylandObjectKind);
+
+	impl WaylandObject for Dummy {
+		fn handle(
+			&self,
+			_: OpCode,
+			_: Vec<WireArgument>,
+		) -> Result<Vec<AppRequest>, WaytinierError> {
+			panic!("event reached a destroyed object")
+		}
+
+		fn kind(&self) -> WaylandObjectKind {
+			self.0
+		}
 	}
-}
 
-#[repr(C)]
-#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
-pub enum PixelFormat {
-	Argb888,
-	Xrgb888,
+	// takes any event and does nothing with it
+	struct Quiet(WaylandObjectKind);
+
+	impl WaylandObject for Quiet {
+		fn handle(
+			&self,
+			_: OpCode,
+			_: Vec<WireArgument>,
+		) -> Result<Vec<AppRequest>, WaytinierError> {
+			Ok(vec![])
+		}
+
+		fn kind(&self) -> WaylandObjectKind {
+			self.0
+		}
+	}
+
+	fn next_event(
+		wlmm: &mut MessageManager,
+		wlim: &IdentManager,
+	) -> Result<WireEventRaw, Box<dyn Error>> {
+		let deadline = Instant::now() + Duration::from_secs(5);
+		loop {
+			wlmm.read_socket()?;
+			if let Some(event) = wlmm.next_event(wlim)? {
+				return Ok(event);
+			}
+			assert!(Instant::now() < deadline, "event never arrived");
+			std::thread::sleep(Duration::from_millis(1));
+		}
+	}
+
+	#[test]
+	fn ids_come_back_after_delete_id() -> Result<(), Box<dyn Error>> {
+		let mut wlim = IdentManager::default();
+		let a = wlim.new_id_registered(rl!(Dummy(WaylandObjectKind::Buffer)));
+		wlim.destroy(a);
+		assert_eq!(wlim.kind_of(a), Some(WaylandObjectKind::Buffer));
+		assert!(wlim.find_obj_by_id(a).is_err());
+
+		// not acknowledged yet, a fresh id has to be used
+		let b = wlim.new_id_registered(rl!(Dummy(WaylandObjectKind::Buffer)));
+		assert_ne!(a, b);
+
+		wlim.delete_id(a)?;
+		assert_eq!(wlim.kind_of(a), None);
+		let c = wlim.new_id_registered(rl!(Dummy(WaylandObjectKind::Callback)));
+		assert_eq!(c, a);
+
+		// a second delete_id for the same id must not free it twice
+		wlim.delete_id(b)?;
+		assert!(wlim.delete_id(b).is_err());
+		assert_eq!(wlim.free.len(), 1);
+		Ok(())
+	}
+
+	#[test]
+	fn zombies_close_late_fds() -> Result<(), Box<dyn Error>> {
+		let (mock, client) = MockCompositor::spawn(&[])?;
+		let mut wlmm = MessageManager::from_stream(client)?;
+		let mut wlim = IdentManager::default();
+		let keyboard = wlim.new_id_registered(rl!(Dummy(WaylandObjectKind::Keyboard)));
+		wlim.destroy(keyboard);
+
+		let mut ends = [0; 2];
+		assert_eq!(
+			unsafe { libc::pipe2(ends.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) },
+			0
+		);
+		let (rd, wr) = unsafe { (OwnedFd::from_raw_fd(ends[0]), OwnedFd::from_raw_fd(ends[1])) };
+
+		// wl_keyboard.keymap arriving after the client released the keyboard
+		let args =
+			vec![WireArgument::UnInt(1), WireArgument::FileDescriptor(wr), WireArgument::UnInt(0)];
+		mock.send_event(keyboard.raw(), 0, args)?;
+
+		let event = next_event(&mut wlmm, &wlim)?;
+		assert_eq!(event.fds.len(), 1);
+		assert!(wlim.dispatch(event)?.is_empty());
+
+		// the last write end is gone, so reading hits eof instead of EAGAIN
+		let mut byte = 0u8;
+		let n = unsafe { libc::read(rd.as_raw_fd(), (&raw mut byte).cast(), 1) };
+		assert_eq!(n, 0);
+		Ok(())
+	}
+
+	#[test]
+	fn server_created_objects() -> Result<(), Box<dyn Error>> {
+		let (mock, client) = MockCompositor::spawn(&[])?;
+		let mut wlmm = MessageManager::from_stream(client)?;
+		let mut wlim = IdentManager::default();
+		let device = wlim.new_id_registered(rl!(Quiet(WaylandObjectKind::DataDevice)));
+		let offer = SERVER_ID_START;
+
+		// wl_data_device.data_offer, then wl_data_offer.offer on the new object
+		mock.send_event(device.raw(), 0, vec![WireArgument::NewId(offer)])?;
+		mock.send_event(offer, 0, vec![WireArgument::String(Some(String::from("text/plain")))])?;
+
+		let event = next_event(&mut wlmm, &wlim)?;
+		wlim.dispatch(event)?;
+		assert_eq!(wlim.kind_of(Id(offer)), Some(WaylandObjectKind::DataOffer));
+		let event = next_event(&mut wlmm, &wlim)?;
+		assert!(matches!(&wlim.dispatch(event)?[..], [AppRequest::DebugMessage(..)]));
+
+		// events that were in flight when it got destroyed are thrown away
+		wlim.destroy(Id(offer));
+		mock.send_event(offer, 0, vec![WireArgument::String(Some(String::from("text/html")))])?;
+		let event = next_event(&mut wlmm, &wlim)?;
+		assert!(wlim.dispatch(event)?.is_empty());
+		assert_eq!(wlim.kind_of(Id(offer)), Some(WaylandObjectKind::DataOffer));
+		Ok(())
+	}
+
+	#[test]
+	fn server_ids_in_order() {
+		let mut wlim = IdentManager::default();
+		let live = || ObjectEntry::Live(rl!(Dummy(WaylandObjectKind::DataOffer)));
+		// not a server id at all
+		assert!(wlim.insert_server(Id(5), live()).is_err());
+		// skipping ahead
+		assert!(wlim.insert_server(Id(SERVER_ID_START + 1), live()).is_err());
+		assert!(wlim.insert_server(Id(SERVER_ID_START), live()).is_ok());
+		// already taken
+		assert!(wlim.insert_server(Id(SERVER_ID_START), live()).is_err());
+		assert!(wlim.insert_server(Id(SERVER_ID_START + 1), live()).is_ok());
+		// the compositor reuses the id of one we destroyed
+		wlim.destroy(Id(SERVER_ID_START));
+		assert!(wlim.insert_server(Id(SERVER_ID_START), live()).is_ok());
+	}
 }
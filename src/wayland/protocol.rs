@@ -0,0 +1,18 @@
+// typed requests, events and enums for every interface in protocols/.
+// the bindings are generated at build time by build/main.rs
+#![allow(dead_code, non_camel_case_types, clippy::enum_variant_names)]
+
+use std::os::fd::OwnedFd;
+
+use crate::wayland::{
+	Fixed, Id, OpCode, Raw, WaylandError,
+	wire::{FromWireArgument, WireArgument, WireArgumentKind, WireRequest},
+};
+
+fn next_arg<T: FromWireArgument>(
+	args: &mut impl Iterator<Item = WireArgument>,
+) -> Result<T, WaylandError> {
+	T::from_arg(args.next().ok_or(WaylandError::MissingArgument)?)
+}
+
+include!(concat!(env!("OUT_DIR"), "/protocol.rs"));
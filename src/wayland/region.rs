@@ -0,0 +1,99 @@
+use crate::{
+	Rl, WaytinierError, rl,
+	wayland::{
+		AppRequest, Id, IdentManager, OpCode, Request, WaylandObject, WaylandObjectKind,
+		protocol::wl_region,
+		wire::{WireArgument, WireRequest},
+	},
+};
+
+// the rectangles of a region, added and subtracted in order. only a
+// description, a wl_region is made from it when it's set on a presenter
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SurfaceRegion {
+	// (subtract, x, y, width, height)
+	pub(crate) rects: Vec<(bool, i32, i32, i32, i32)>,
+}
+
+impl SurfaceRegion {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn add(mut self, x: i32, y: i32, width: i32, height: i32) -> Self {
+		self.rects.push((false, x, y, width, height));
+		self
+	}
+
+	pub fn subtract(mut self, x: i32, y: i32, width: i32, height: i32) -> Self {
+		self.rects.push((true, x, y, width, height));
+		self
+	}
+}
+
+// a set of rectangles, used for the opaque and input regions of a surface.
+// surfaces copy it when it's set, so it can be destroyed right after
+pub(crate) struct Region {
+	pub(crate) id: Id,
+	pub(crate) version: u32,
+}
+
+impl Region {
+	pub(crate) fn new_registered(wlim: &mut IdentManager, version: u32) -> Rl<Self> {
+		let region = rl!(Self {
+			id: Id(0),
+			version,
+		});
+		let id = wlim.new_id_registered(region.clone());
+		region.borrow_mut().id = id;
+		region
+	}
+
+	fn request(&self, inner: WireRequest, opname: &'static str) -> Vec<AppRequest> {
+		vec![AppRequest::Request(Request {
+			inner,
+			opname,
+			kind: self.kind_str(),
+		})]
+	}
+
+	pub(crate) fn add(&self, x: i32, y: i32, width: i32, height: i32) -> Vec<AppRequest> {
+		self.request(wl_region::add(self.id, x, y, width, height), "add")
+	}
+
+	pub(crate) fn subtract(&self, x: i32, y: i32, width: i32, height: i32) -> Vec<AppRequest> {
+		self.request(wl_region::subtract(self.id, x, y, width, height), "subtract")
+	}
+
+	// the rectangles of the description, in its order
+	pub(crate) fn fill(&self, shape: &SurfaceRegion) -> Vec<AppRequest> {
+		let rects = shape.rects.iter().map(|&(subtract, x, y, width, height)| match subtract {
+			true => self.subtract(x, y, width, height),
+			false => self.add(x, y, width, height),
+		});
+		rects.flatten().collect()
+	}
+
+	pub(crate) fn destroy(&self) -> Vec<AppRequest> {
+		self.request(wl_region::destroy(self.id), "destroy")
+	}
+}
+
+impl WaylandObject for Region {
+	fn handle(
+		&self,
+		opcode: OpCode,
+		args: Vec<WireArgument>,
+	) -> Result<Vec<AppRequest>, WaytinierError> {
+		// wl_region has no events
+		match wl_region::Event::from_args(opcode, args)? {}
+	}
+
+	fn kind(&self) -> WaylandObjectKind {
+		WaylandObjectKind::Region
+	}
+
+	fn version(&self) -> u32 {
+		self.version
+	}
+}
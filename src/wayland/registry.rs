@@ -1,11 +1,12 @@
-use std::{collections::HashMap, error::Error, fmt::Display, os::fd::OwnedFd};
+use std::{collections::HashMap, fmt::Display, ops::RangeInclusive};
 
 use crate::{
-	NONE, Rl, WHITE, rl,
+	NONE, Rl, WHITE, WaytinierError, rl,
 	wayland::{
-		AppRequest, Boxed, DebugLevel, Id, IdentManager, OpCode, Raw, Request, WaylandError,
+		AppRequest, DebugLevel, Id, IdentManager, OpCode, Raw, Request, WaylandError,
 		WaylandObject, WaylandObjectKind,
-		wire::{FromWirePayload, WireArgument, WireRequest},
+		protocol::wl_registry,
+		wire::{WireArgument, WireRequest},
 	},
 	wlog,
 };
@@ -52,61 +53,69 @@ impl Registry {
 	}
 
 	fn wl_bind(&self, id: Id, object: u32, name: &'static str, version: u32) -> WireRequest {
-		WireRequest {
-			sender_id: self.id,
-			opcode: OpCode(0),
-			args: vec![
-				WireArgument::UnInt(object),
-				WireArgument::NewIdSpecific(name, version, id.raw()),
-			],
-		}
+		wl_registry::bind(self.id, object, name, version, id)
+	}
+
+	// the global with the lowest name when there are several of an interface,
+	// so the pick doesn't depend on the order of the map. that's usually the
+	// one the compositor announced first
+	fn global(&self, interface: &str) -> Option<(RegistryName, u32)> {
+		self.inner
+			.iter()
+			.filter(|(_, v)| v.interface == interface)
+			.min_by_key(|(k, _)| k.raw())
+			.map(|(k, v)| (*k, v.version))
 	}
 
+	// binds the global of the given kind at the highest version both sides
+	// know, returns the requests to send and the version picked
 	pub(crate) fn bind(
 		&mut self,
 		id: Id,
 		kind: WaylandObjectKind,
-		version: u32,
-	) -> Result<Vec<AppRequest>, Box<dyn Error>> {
-		let global_id = self
-			.inner
-			.iter()
-			.find(|(_, v)| v.interface == kind.as_str())
-			.map(|(k, _)| k)
-			.copied()
-			.ok_or(WaylandError::NotInRegistry(kind))?;
+		versions: RangeInclusive<u32>,
+	) -> Result<(Vec<AppRequest>, u32), WaytinierError> {
+		let (global_id, advertised) =
+			self.global(kind.as_str()).ok_or(WaylandError::NotInRegistry(kind.as_str()))?;
+		if advertised < *versions.start() {
+			return Err(
+				WaylandError::VersionTooOld(kind.as_str(), advertised, *versions.start()).into()
+			);
+		}
+		let version = advertised.min(*versions.end());
 		wlog!(
 			DebugLevel::Important,
 			self.kind_str(),
-			format!("bind global id for {}: {}", kind.as_str(), global_id),
+			format!("bind global id for {}: {} at version {}", kind.as_str(), global_id, version),
 			WHITE,
 			NONE
 		);
-		Ok(vec![AppRequest::Request(Request {
+		let pending = vec![AppRequest::Request(Request {
 			inner: self.wl_bind(id, global_id.raw(), kind.as_str(), version),
 			opname: "bind",
-			kind: kind.as_str(),
-		})])
+			kind: self.kind_str(),
+		})];
+		Ok((pending, version))
 	}
 
 	pub fn does_implement(&self, query: &str) -> Option<u32> {
-		self.inner.iter().find(|(_, v)| v.interface == query).map(|(_, v)| v.version)
+		self.global(query).map(|(_, version)| version)
 	}
 }
 
 impl WaylandObject for Registry {
 	fn handle(
 		&self,
-		p: &[u8],
 		opcode: OpCode,
-		_fds: Vec<OwnedFd>,
-	) -> Result<Vec<AppRequest>, Box<dyn Error>> {
+		args: Vec<WireArgument>,
+	) -> Result<Vec<AppRequest>, WaytinierError> {
 		let mut pending = vec![];
-		match opcode.raw() {
-			0 => {
-				let name = u32::from_wire(p)?;
-				let interface = String::from_wire(&p[4..])?;
-				let version = u32::from_wire(&p[p.len() - 4..])?;
+		match wl_registry::Event::from_args(opcode, args)? {
+			wl_registry::Event::Global {
+				name,
+				interface,
+				version,
+			} => {
 				let msg = format!("inserted interface {} version {}", interface, version);
 				pending.push(AppRequest::RegistryPush(
 					RegistryName(name),
@@ -117,13 +126,15 @@ impl WaylandObject for Registry {
 				));
 				pending.push(AppRequest::DebugMessage(DebugLevel::Trivial, msg));
 			}
-			// can global_remove even happen
-			1 => {
-				// let name = decode_event_payload(&p[8..], WireArgumentKind::UnInt)?;
-				todo!()
-			}
-			inv => {
-				return Err(WaylandError::InvalidOpCode(OpCode(inv), self.kind_str()).boxed());
+			// outputs and seats come and go at runtime
+			wl_registry::Event::GlobalRemove {
+				name,
+			} => {
+				pending.push(AppRequest::RegistryRemove(RegistryName(name)));
+				pending.push(AppRequest::DebugMessage(
+					DebugLevel::Trivial,
+					format!("global {name} removed"),
+				));
 			}
 		}
 		Ok(pending)
@@ -137,3 +148,31 @@ impl WaylandObject for Registry {
 		self.kind().as_str()
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn binds_the_lowest_name() -> Result<(), WaytinierError> {
+		let reg = Registry::new(Id(2));
+		let mut reg = reg.borrow_mut();
+		for (name, version) in [(9, 4), (3, 2), (12, 4), (5, 3)] {
+			reg.inner.insert(
+				RegistryName(name),
+				RegistryEntry {
+					interface: String::from("wl_output"),
+					version,
+				},
+			);
+		}
+		assert_eq!(reg.does_implement("wl_output"), Some(2));
+		let (pending, version) = reg.bind(Id(3), WaylandObjectKind::Output, 1..=4)?;
+		assert_eq!(version, 2);
+		let [AppRequest::Request(req)] = &pending[..] else {
+			panic!("expected one bind request");
+		};
+		assert!(matches!(req.inner.args[0], WireArgument::UnInt(3)));
+		Ok(())
+	}
+}
@@ -1,9 +1,488 @@
-use crate::wayland::buffer::BufferBackend;
+use std::{
+	cell::{Cell, RefCell},
+	ffi::CStr,
+	io,
+	os::fd::{AsRawFd, FromRawFd, OwnedFd},
+	ptr::NonNull,
+};
 
-pub struct ShmBackend {}
+use crate::{
+	Rl, WaytinierError, rl,
+	wayland::{
+		AppRequest, DebugLevel, Id, IdentManager, OpCode, PixelFormat, Request, WaylandError,
+		WaylandObject, WaylandObjectKind,
+		buffer::BufferBackend,
+		protocol::{wl_buffer, wl_shm, wl_shm_pool},
+		registry::Registry,
+		wire::WireArgument,
+	},
+};
 
+// an anonymous memfd mapped into our address space. the compositor gets a
+// dup of the fd and maps the same pages
+pub(crate) struct ShmMap {
+	fd: OwnedFd,
+	ptr: NonNull<u8>,
+	len: usize,
+}
+
+impl ShmMap {
+	pub(crate) fn new(len: usize) -> io::Result<Self> {
+		if len == 0 {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty shm map"));
+		}
+		let name: &CStr = c"waytinier-shm";
+		let raw = unsafe {
+			libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING)
+		};
+		if raw < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+		if unsafe { libc::ftruncate(fd.as_raw_fd(), len as libc::off_t) } < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		// the compositor may rely on the file never shrinking under it, same
+		// seals libwayland's os_create_anonymous_file sets
+		let seals = libc::F_SEAL_SHRINK | libc::F_SEAL_SEAL;
+		if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_ADD_SEALS, seals) } < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		let ptr = unsafe {
+			libc::mmap(
+				std::ptr::null_mut(),
+				len,
+				libc::PROT_READ | libc::PROT_WRITE,
+				libc::MAP_SHARED,
+				fd.as_raw_fd(),
+				0,
+			)
+		};
+		if ptr == libc::MAP_FAILED {
+			return Err(io::Error::last_os_error());
+		}
+		Ok(Self {
+			fd,
+			// mmap never hands out null on success
+			ptr: NonNull::new(ptr.cast()).ok_or_else(io::Error::last_os_error)?,
+			len,
+		})
+	}
+
+	pub(crate) fn len(&self) -> usize {
+		self.len
+	}
+
+	// grows the file and the mapping, the seals only forbid shrinking.
+	// the mapping may move
+	pub(crate) fn grow(&mut self, len: usize) -> io::Result<()> {
+		if len <= self.len {
+			return Ok(());
+		}
+		if unsafe { libc::ftruncate(self.fd.as_raw_fd(), len as libc::off_t) } < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		let ptr =
+			unsafe { libc::mremap(self.ptr.as_ptr().cast(), self.len, len, libc::MREMAP_MAYMOVE) };
+		if ptr == libc::MAP_FAILED {
+			return Err(io::Error::last_os_error());
+		}
+		self.ptr = NonNull::new(ptr.cast()).ok_or_else(io::Error::last_os_error)?;
+		self.len = len;
+		Ok(())
+	}
+
+	pub(crate) fn dup_fd(&self) -> io::Result<OwnedFd> {
+		self.fd.try_clone()
+	}
+
+	pub(crate) fn as_mut_slice(&mut self) -> &mut [u8] {
+		// the mapping lives as long as self and only we hand out references
+		unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+	}
+}
+
+impl Drop for ShmMap {
+	fn drop(&mut self) {
+		unsafe { libc::munmap(self.ptr.as_ptr().cast(), self.len) };
+	}
+}
+
+// the wl_shm global
+pub(crate) struct SharedMemory {
+	pub(crate) id: Id,
+	pub(crate) version: u32,
+	// wl_shm format codes, sent right after binding
+	pub(crate) formats: RefCell<Vec<u32>>,
+}
+
+impl SharedMemory {
+	pub(crate) fn new(id: Id) -> Self {
+		Self {
+			id,
+			version: 1,
+			formats: RefCell::new(vec![]),
+		}
+	}
+
+	pub(crate) fn supports(&self, pf: PixelFormat) -> bool {
+		self.formats.borrow().contains(&pf.wl_shm_format())
+	}
+
+	pub(crate) fn new_bound(
+		wlim: &mut IdentManager,
+		registry: &Rl<Registry>,
+	) -> Result<(Vec<AppRequest>, Rl<Self>), WaytinierError> {
+		let shm = rl!(Self::new(Id(0)));
+		let id = wlim.new_id_registered(shm.clone());
+		shm.borrow_mut().id = id;
+		let (pending, version) =
+			registry.borrow_mut().bind(id, WaylandObjectKind::Shm, 1..=wl_shm::VERSION)?;
+		shm.borrow_mut().version = version;
+		Ok((pending, shm))
+	}
+
+	// a new pool of size bytes, the fd goes out with the request
+	pub(crate) fn create_pool(
+		&self,
+		wlim: &mut IdentManager,
+		size: usize,
+	) -> Result<(Vec<AppRequest>, Rl<ShmPool>), WaytinierError> {
+		let size_i32 = i32::try_from(size).map_err(|_| WaylandError::BadShmSize(size))?;
+		let map = ShmMap::new(size)?;
+		let fd = map.dup_fd()?;
+		let pool = rl!(ShmPool {
+			id: Id(0),
+			version: self.version,
+			map,
+		});
+		let id = wlim.new_id_registered(pool.clone());
+		pool.borrow_mut().id = id;
+		Ok((
+			vec![AppRequest::Request(Request {
+				inner: wl_shm::create_pool(self.id, id, fd, size_i32),
+				opname: "create_pool",
+				kind: self.kind_str(),
+			})],
+			pool,
+		))
+	}
+}
+
+impl WaylandObject for SharedMemory {
+	fn handle(
+		&self,
+		opcode: OpCode,
+		args: Vec<WireArgument>,
+	) -> Result<Vec<AppRequest>, WaytinierError> {
+		match wl_shm::Event::from_args(opcode, args)? {
+			wl_shm::Event::Format {
+				format,
+			} => {
+				self.formats.borrow_mut().push(format);
+				let name = PixelFormat::from_wl_shm_format(format)
+					.map_or(format!("{format:#010x}"), |pf| format!("{pf:?}"));
+				Ok(vec![AppRequest::DebugMessage(
+					DebugLevel::Trivial,
+					format!("shm supports format {name}"),
+				)])
+			}
+		}
+	}
+
+	fn kind(&self) -> WaylandObjectKind {
+		WaylandObjectKind::Shm
+	}
+
+	fn version(&self) -> u32 {
+		self.version
+	}
+}
+
+// a wl_shm_pool together with the memory behind it
+pub(crate) struct ShmPool {
+	pub(crate) id: Id,
+	pub(crate) version: u32,
+	pub(crate) map: ShmMap,
+}
+
+impl ShmPool {
+	// a buffer in the pool starting at offset. the whole buffer has to lie
+	// inside the pool, the compositor kills the connection otherwise
+	pub(crate) fn create_buffer(
+		&self,
+		wlim: &mut IdentManager,
+		offset: usize,
+		width: i32,
+		height: i32,
+		stride: i32,
+		format: u32,
+	) -> Result<(Vec<AppRequest>, Rl<ShmBuffer>), WaytinierError> {
+		let len = buffer_len(width, height, stride)?;
+		if offset.checked_add(len).is_none_or(|end| end > self.map.len()) {
+			return Err(WaylandError::BufferOutsidePool(offset, len, self.map.len()).into());
+		}
+		let buffer = rl!(ShmBuffer {
+			id: Id(0),
+			version: self.version,
+			offset,
+			height,
+			stride,
+			busy: Cell::new(false),
+		});
+		let id = wlim.new_id_registered(buffer.clone());
+		buffer.borrow_mut().id = id;
+		Ok((
+			vec![AppRequest::Request(Request {
+				inner: wl_shm_pool::create_buffer(
+					self.id,
+					id,
+					offset as i32,
+					width,
+					height,
+					stride,
+					format,
+				),
+				opname: "create_buffer",
+				kind: self.kind_str(),
+			})],
+			buffer,
+		))
+	}
+
+	// the pixels of a buffer from this pool
+	pub(crate) fn canvas(&mut self, buffer: &ShmBuffer) -> &mut [u8] {
+		&mut self.map.as_mut_slice()[buffer.offset..buffer.offset + buffer.len()]
+	}
+
+	// pools can only grow, the compositor remaps once it gets the resize
+	pub(crate) fn resize(&mut self, size: usize) -> Result<Vec<AppRequest>, WaytinierError> {
+		if size <= self.map.len() {
+			return Ok(vec![]);
+		}
+		let size_i32 = i32::try_from(size).map_err(|_| WaylandError::BadShmSize(size))?;
+		self.map.grow(size)?;
+		Ok(vec![AppRequest::Request(Request {
+			inner: wl_shm_pool::resize(self.id, size_i32),
+			opname: "resize",
+			kind: self.kind_str(),
+		})])
+	}
+
+	// the compositor keeps its mapping until every buffer is gone too
+	pub(crate) fn destroy(&self) -> Vec<AppRequest> {
+		vec![AppRequest::Request(Request {
+			inner: wl_shm_pool::destroy(self.id),
+			opname: "destroy",
+			kind: self.kind_str(),
+		})]
+	}
+}
+
+impl WaylandObject for ShmPool {
+	fn handle(
+		&self,
+		opcode: OpCode,
+		args: Vec<WireArgument>,
+	) -> Result<Vec<AppRequest>, WaytinierError> {
+		// wl_shm_pool has no events
+		match wl_shm_pool::Event::from_args(opcode, args)? {}
+	}
+
+	fn kind(&self) -> WaylandObjectKind {
+		WaylandObjectKind::ShmPool
+	}
+
+	fn version(&self) -> u32 {
+		self.version
+	}
+}
+
+pub(crate) fn buffer_len(width: i32, height: i32, stride: i32) -> Result<usize, WaylandError> {
+	let bad = || WaylandError::BadBufferSize(width, height);
+	let rows = usize::try_from(height).map_err(|_| bad())?;
+	let row = usize::try_from(stride).map_err(|_| bad())?;
+	rows.checked_mul(row).ok_or_else(bad)
+}
+
+// a wl_buffer living in a shm pool
+pub(crate) struct ShmBuffer {
+	pub(crate) id: Id,
+	pub(crate) version: u32,
+	pub(crate) offset: usize,
+	pub(crate) height: i32,
+	pub(crate) stride: i32,
+	// the compositor may read from it until it sends release
+	pub(crate) busy: Cell<bool>,
+}
+
+impl ShmBuffer {
+	pub(crate) fn len(&self) -> usize {
+		self.height as usize * self.stride as usize
+	}
+
+	// the first byte past the buffer in its pool
+	pub(crate) fn end(&self) -> usize {
+		self.offset + self.len()
+	}
+
+	pub(crate) fn destroy(&self) -> Vec<AppRequest> {
+		vec![AppRequest::Request(Request {
+			inner: wl_buffer::destroy(self.id),
+			opname: "destroy",
+			kind: self.kind_str(),
+		})]
+	}
+}
+
+impl WaylandObject for ShmBuffer {
+	fn handle(
+		&self,
+		opcode: OpCode,
+		args: Vec<WireArgument>,
+	) -> Result<Vec<AppRequest>, WaytinierError> {
+		match wl_buffer::Event::from_args(opcode, args)? {
+			wl_buffer::Event::Release => {
+				self.busy.set(false);
+				Ok(vec![])
+			}
+		}
+	}
+
+	fn kind(&self) -> WaylandObjectKind {
+		WaylandObjectKind::Buffer
+	}
+
+	fn version(&self) -> u32 {
+		self.version
+	}
+}
+
+// draws into shared memory the compositor reads from. wl_shm is optional, if
+// the compositor doesn't have it pools can't be made
+pub struct ShmBackend {
+	pub(crate) shm: Option<Rl<SharedMemory>>,
+}
+
+impl ShmBackend {
+	pub(crate) fn create_pool(
+		&self,
+		wlim: &mut IdentManager,
+		size: usize,
+	) -> Result<(Vec<AppRequest>, Rl<ShmPool>), WaytinierError> {
+		let shm = self.shm.as_ref().ok_or(WaylandError::NotInRegistry(wl_shm::NAME))?;
+		shm.borrow().create_pool(wlim, size)
+	}
+
+	// the first of the wanted formats the compositor supports. argb8888 is
+	// the fallback, every compositor has to support it
+	pub(crate) fn pick_format(&self, wanted: &[PixelFormat]) -> PixelFormat {
+		let Some(shm) = &self.shm else {
+			return PixelFormat::Argb8888;
+		};
+		let shm = shm.borrow();
+		wanted.iter().copied().find(|pf| shm.supports(*pf)).unwrap_or(PixelFormat::Argb8888)
+	}
+}
+
+#[allow(private_interfaces)]
 impl BufferBackend for ShmBackend {
 	fn new() -> Self {
-		Self {}
+		Self {
+			shm: None,
+		}
+	}
+
+	fn bind(
+		&mut self,
+		wlim: &mut IdentManager,
+		registry: &Rl<Registry>,
+	) -> Result<Vec<AppRequest>, WaytinierError> {
+		if registry.borrow().does_implement(wl_shm::NAME).is_none() {
+			return Ok(vec![]);
+		}
+		let (pending, shm) = SharedMemory::new_bound(wlim, registry)?;
+		self.shm = Some(shm);
+		Ok(pending)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::os::unix::fs::FileExt;
+
+	use super::*;
+	use crate::testing::{
+		MockCompositor,
+		fixtures::{TestApp, connect_app},
+	};
+
+	#[test]
+	fn maps_are_sealed_and_shared() -> Result<(), WaytinierError> {
+		let mut map = ShmMap::new(4096)?;
+		map.as_mut_slice()[100] = 0xab;
+		let file = std::fs::File::from(map.dup_fd()?);
+		let mut byte = [0];
+		file.read_exact_at(&mut byte, 100)?;
+		assert_eq!(byte, [0xab]);
+
+		let seals = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GET_SEALS) };
+		assert_eq!(seals, libc::F_SEAL_SHRINK | libc::F_SEAL_SEAL);
+		// growing is fine, shrinking isn't
+		assert!(file.set_len(2048).is_err());
+		assert!(ShmMap::new(0).is_err());
+		Ok(())
+	}
+
+	#[test]
+	fn pixels_reach_the_compositor() -> Result<(), WaytinierError> {
+		let (mock, mut app) = connect_app(&[("wl_compositor", 6), ("wl_shm", 2)])?;
+		mock.take_requests();
+
+		let (pending, pool) = app.backend.create_pool(&mut app.wlim, 2 * 4 * 4)?;
+		app.handle_requests(pending)?;
+		let (pending, buffer) = pool.borrow().create_buffer(&mut app.wlim, 16, 2, 2, 8, 0)?;
+		app.handle_requests(pending)?;
+		pool.borrow_mut().canvas(&buffer.borrow()).fill(0x7f);
+		// the buffer has to fit the pool
+		assert!(pool.borrow().create_buffer(&mut app.wlim, 24, 2, 2, 8, 0).is_err());
+		app.roundtrip()?;
+
+		let mut requests = mock.take_requests().into_iter();
+		let create_pool = requests.next().unwrap();
+		assert_eq!(
+			create_pool.label(),
+			format!("wl_shm@{}.create_pool", app.backend.shm.unwrap().borrow().id)
+		);
+		let [_, WireArgument::FileDescriptor(fd), WireArgument::Int(32)] = &create_pool.args[..]
+		else {
+			panic!("{:?}", create_pool.args);
+		};
+		let mut pixels = [0; 32];
+		std::fs::File::from(fd.try_clone()?).read_exact_at(&mut pixels, 0)?;
+		assert_eq!(pixels[..16], [0; 16]);
+		assert_eq!(pixels[16..], [0x7f; 16]);
+
+		let create_buffer = requests.next().unwrap();
+		assert_eq!(create_buffer.name, "create_buffer");
+		assert_eq!(mock.object_interface(buffer.borrow().id.0), Some("wl_buffer"));
+		Ok(())
+	}
+
+	#[test]
+	fn formats_fall_back() -> Result<(), WaytinierError> {
+		let (mock, client) = MockCompositor::spawn(&[("wl_compositor", 6), ("wl_shm", 2)])?;
+		mock.set_shm_formats(&[0, 1, PixelFormat::Rgb565.wl_shm_format()]);
+		let app = TestApp::from_stream(client)?;
+		let shm = app.backend.shm.as_ref().unwrap();
+		// the formats come right after the bind, the connect roundtrip got them
+		assert_eq!(*shm.borrow().formats.borrow(), [0, 1, PixelFormat::Rgb565.wl_shm_format()]);
+
+		let pick = |wanted: &[PixelFormat]| app.backend.pick_format(wanted);
+		assert_eq!(pick(&[PixelFormat::Rgb565]), PixelFormat::Rgb565);
+		assert_eq!(pick(&[PixelFormat::Xrgb2101010, PixelFormat::Xrgb8888]), PixelFormat::Xrgb8888);
+		assert_eq!(pick(&[PixelFormat::Xrgb2101010]), PixelFormat::Argb8888);
+		assert_eq!(pick(&[]), PixelFormat::Argb8888);
+		Ok(())
 	}
 }
@@ -1,33 +1,392 @@
+use std::cell::{Cell, RefCell};
+
 use crate::{
-	Rl, rl,
-	wayland::{Id, PixelFormat, WaylandObject},
+	DebugLevel, Rl, WaytinierError, rl,
+	wayland::{
+		AppRequest, Id, IdentManager, OpCode, PixelFormat, Request, WaylandObject,
+		WaylandObjectKind,
+		callback::Callback,
+		protocol::{wl_output, wl_surface},
+		region::Region,
+		shm::ShmBuffer,
+		wire::{WireArgument, WireRequest},
+	},
 };
 
-pub(crate) struct Surface {
+// a rectangle the compositor can show. nothing it gets asked to do is visible
+// until commit, which applies all of it at once
+pub struct Surface {
 	pub(crate) id: Id,
 	pub(crate) pf: PixelFormat,
+	pub(crate) version: u32,
+	// the scale set with set_buffer_scale, damage_buffer falls back to
+	// surface coordinates with it on old compositors
+	pub(crate) scale: Cell<i32>,
+	// outputs the surface is on, from enter and leave
+	pub(crate) outputs: RefCell<Vec<Id>>,
+	pub(crate) preferred_scale: Cell<i32>,
+	pub(crate) preferred_transform: Cell<Transform>,
+}
+
+// how the buffer is turned before it's shown, counterclockwise. the flipped
+// ones are mirrored around the vertical axis first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+	Normal,
+	Rotate90,
+	Rotate180,
+	Rotate270,
+	Flipped,
+	Flipped90,
+	Flipped180,
+	Flipped270,
+}
+
+impl Transform {
+	pub(crate) fn from_wl(transform: wl_output::Transform) -> Self {
+		match transform {
+			wl_output::Transform::Normal => Transform::Normal,
+			wl_output::Transform::_90 => Transform::Rotate90,
+			wl_output::Transform::_180 => Transform::Rotate180,
+			wl_output::Transform::_270 => Transform::Rotate270,
+			wl_output::Transform::Flipped => Transform::Flipped,
+			wl_output::Transform::Flipped90 => Transform::Flipped90,
+			wl_output::Transform::Flipped180 => Transform::Flipped180,
+			wl_output::Transform::Flipped270 => Transform::Flipped270,
+		}
+	}
+
+	pub(crate) fn to_wl(self) -> wl_output::Transform {
+		match self {
+			Transform::Normal => wl_output::Transform::Normal,
+			Transform::Rotate90 => wl_output::Transform::_90,
+			Transform::Rotate180 => wl_output::Transform::_180,
+			Transform::Rotate270 => wl_output::Transform::_270,
+			Transform::Flipped => wl_output::Transform::Flipped,
+			Transform::Flipped90 => wl_output::Transform::Flipped90,
+			Transform::Flipped180 => wl_output::Transform::Flipped180,
+			Transform::Flipped270 => wl_output::Transform::Flipped270,
+		}
+	}
 }
 
 impl Surface {
-	pub(crate) fn new(id: Id, pf: PixelFormat) -> Rl<Self> {
+	pub(crate) fn new(id: Id, pf: PixelFormat, version: u32) -> Rl<Self> {
 		rl!(Self {
 			id,
 			pf,
+			version,
+			scale: Cell::new(1),
+			outputs: RefCell::new(vec![]),
+			preferred_scale: Cell::new(1),
+			preferred_transform: Cell::new(Transform::Normal),
 		})
 	}
+
+	pub fn pixel_format(&self) -> PixelFormat {
+		self.pf
+	}
+
+	// what the compositor would like set_buffer_scale to be. 1 until it says
+	// otherwise, and always 1 before wl_compositor version 6
+	pub fn preferred_buffer_scale(&self) -> i32 {
+		self.preferred_scale.get()
+	}
+
+	// what the compositor would like set_buffer_transform to be, normal
+	// until it says otherwise
+	pub fn preferred_buffer_transform(&self) -> Transform {
+		self.preferred_transform.get()
+	}
+
+	// the wl_output ids of the outputs the surface is on, oldest first
+	pub fn outputs(&self) -> Vec<u32> {
+		self.outputs.borrow().iter().map(|id| id.0).collect()
+	}
+
+	fn request(&self, inner: WireRequest, opname: &'static str) -> Vec<AppRequest> {
+		vec![AppRequest::Request(Request {
+			inner,
+			opname,
+			kind: self.kind_str(),
+		})]
+	}
+
+	// shows the buffer from the next commit on. the compositor reads from it
+	// until it sends release, it's busy until then. since version 5 a nonzero
+	// x and y is a protocol error and has to go through offset instead
+	pub(crate) fn attach(&self, buffer: &ShmBuffer, x: i32, y: i32) -> Vec<AppRequest> {
+		buffer.busy.set(true);
+		if self.version < 5 || (x, y) == (0, 0) {
+			return self.request(wl_surface::attach(self.id, Some(buffer.id), x, y), "attach");
+		}
+		let mut pending =
+			self.request(wl_surface::attach(self.id, Some(buffer.id), 0, 0), "attach");
+		pending.extend(self.offset(x, y));
+		pending
+	}
+
+	// damage in surface coordinates, prefer damage_buffer
+	pub(crate) fn damage(&self, x: i32, y: i32, width: i32, height: i32) -> Vec<AppRequest> {
+		self.request(wl_surface::damage(self.id, x, y, width, height), "damage")
+	}
+
+	// damage in buffer pixels. compositors older than version 4 only know
+	// surface coordinates, there it's divided by the buffer scale, rounding
+	// outwards
+	pub(crate) fn damage_buffer(&self, x: i32, y: i32, width: i32, height: i32) -> Vec<AppRequest> {
+		if self.version >= 4 {
+			return self
+				.request(wl_surface::damage_buffer(self.id, x, y, width, height), "damage_buffer");
+		}
+		let scale = self.scale.get().max(1);
+		let (left, top) = (x.div_euclid(scale), y.div_euclid(scale));
+		let right = (x + width + scale - 1).div_euclid(scale);
+		let bottom = (y + height + scale - 1).div_euclid(scale);
+		self.damage(left, top, right - left, bottom - top)
+	}
+
+	// asks for a callback when it's a good time to draw the next frame. it
+	// belongs to the next commit
+	pub(crate) fn frame(&self, wlim: &mut IdentManager) -> (Vec<AppRequest>, Rl<Callback>) {
+		let cb = Callback::new_registered(wlim);
+		let id = cb.borrow().id;
+		(self.request(wl_surface::frame(self.id, id), "frame"), cb)
+	}
+
+	pub(crate) fn commit(&self) -> Vec<AppRequest> {
+		self.request(wl_surface::commit(self.id), "commit")
+	}
+
+	// none means the surface isn't opaque anywhere. the region is copied, it
+	// can be destroyed right after
+	pub(crate) fn set_opaque_region(&self, region: Option<&Region>) -> Vec<AppRequest> {
+		self.request(
+			wl_surface::set_opaque_region(self.id, region.map(|r| r.id)),
+			"set_opaque_region",
+		)
+	}
+
+	// none means the whole surface takes input
+	pub(crate) fn set_input_region(&self, region: Option<&Region>) -> Vec<AppRequest> {
+		self.request(
+			wl_surface::set_input_region(self.id, region.map(|r| r.id)),
+			"set_input_region",
+		)
+	}
+
+	// these two and offset fail in handle_requests on compositors too old
+	// for them
+	pub(crate) fn set_buffer_scale(&self, scale: i32) -> Vec<AppRequest> {
+		self.scale.set(scale);
+		self.request(wl_surface::set_buffer_scale(self.id, scale), "set_buffer_scale")
+	}
+
+	pub(crate) fn set_buffer_transform(&self, transform: Transform) -> Vec<AppRequest> {
+		self.request(
+			wl_surface::set_buffer_transform(self.id, transform.to_wl() as i32),
+			"set_buffer_transform",
+		)
+	}
+
+	// moves the surface relative to where it is now, from the next commit on
+	pub(crate) fn offset(&self, x: i32, y: i32) -> Vec<AppRequest> {
+		self.request(wl_surface::offset(self.id, x, y), "offset")
+	}
+
+	pub(crate) fn destroy(&self) -> Vec<AppRequest> {
+		self.request(wl_surface::destroy(self.id), "destroy")
+	}
 }
 
 impl WaylandObject for Surface {
 	fn handle(
 		&self,
-		payload: &[u8],
-		opcode: super::OpCode,
-		_fds: Vec<std::os::unix::prelude::OwnedFd>,
-	) -> Result<Vec<super::AppRequest>, Box<dyn std::error::Error>> {
-		todo!()
+		opcode: OpCode,
+		args: Vec<WireArgument>,
+	) -> Result<Vec<AppRequest>, WaytinierError> {
+		let msg = match wl_surface::Event::from_args(opcode, args)? {
+			wl_surface::Event::Enter {
+				output,
+			} => {
+				self.outputs.borrow_mut().push(output);
+				format!("surface {} entered output {output}", self.id)
+			}
+			wl_surface::Event::Leave {
+				output,
+			} => {
+				self.outputs.borrow_mut().retain(|o| *o != output);
+				format!("surface {} left output {output}", self.id)
+			}
+			wl_surface::Event::PreferredBufferScale {
+				factor,
+			} => {
+				self.preferred_scale.set(factor);
+				format!("surface {} prefers buffer scale {factor}", self.id)
+			}
+			wl_surface::Event::PreferredBufferTransform {
+				transform,
+			} => {
+				// unknown values are a compositor bug, keep the old one
+				if let Some(t) = wl_output::Transform::from_raw(transform) {
+					self.preferred_transform.set(Transform::from_wl(t));
+				}
+				format!("surface {} prefers buffer transform {transform}", self.id)
+			}
+		};
+		Ok(vec![AppRequest::DebugMessage(DebugLevel::Verbose, msg)])
+	}
+
+	fn kind(&self) -> WaylandObjectKind {
+		WaylandObjectKind::Surface
+	}
+
+	fn version(&self) -> u32 {
+		self.version
 	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		testing::fixtures::{TestApp, surface_app},
+		wayland::swapchain::Swapchain,
+	};
+
+	fn buffer(app: &mut TestApp) -> Result<(Swapchain, Rl<ShmBuffer>), WaytinierError> {
+		let (pending, mut chain) =
+			Swapchain::new(&app.backend, &mut app.wlim, 4, 4, PixelFormat::Xrgb8888)?;
+		app.handle_requests(pending)?;
+		let (pending, buffer) = chain.acquire(&mut app.wlim)?;
+		app.handle_requests(pending)?;
+		Ok((chain, buffer))
+	}
+
+	#[test]
+	fn commit_pipeline() -> Result<(), WaytinierError> {
+		let (mock, mut app, surface) = surface_app(6)?;
+		let (_chain, buffer) = buffer(&mut app)?;
+		mock.take_requests();
+
+		let s = surface.borrow();
+		app.handle_requests(s.attach(&buffer.borrow(), 0, 0))?;
+		app.handle_requests(s.damage_buffer(0, 0, 4, 4))?;
+		let (pending, cb) = s.frame(&mut app.wlim);
+		app.handle_requests(pending)?;
+		app.handle_requests(s.commit())?;
+		app.roundtrip()?;
+
+		let id = s.id;
+		let names: Vec<_> = mock
+			.take_requests()
+			.into_iter()
+			.filter(|req| req.object == id.0)
+			.map(|req| req.name)
+			.collect();
+		assert_eq!(names, ["attach", "damage_buffer", "frame", "commit"]);
+		assert!(buffer.borrow().busy.get());
+		// the mock fires frame callbacks right on commit
+		assert!(cb.borrow().done().is_some());
+		Ok(())
+	}
+
+	#[test]
+	fn attach_offset_goes_through_offset() -> Result<(), WaytinierError> {
+		for (version, expected) in [(5, &["attach", "offset"][..]), (4, &["attach"][..])] {
+			let (mock, mut app, surface) = surface_app(version)?;
+			let (_chain, buffer) = buffer(&mut app)?;
+			mock.take_requests();
+			app.handle_requests(surface.borrow().attach(&buffer.borrow(), 3, 4))?;
+			app.roundtrip()?;
+
+			let reqs: Vec<_> = mock
+				.take_requests()
+				.into_iter()
+				.filter(|req| req.object == surface.borrow().id.0)
+				.collect();
+			let names: Vec<_> = reqs.iter().map(|req| req.name).collect();
+			assert_eq!(names, expected);
+			let xy = if version >= 5 {
+				(0, 0)
+			} else {
+				(3, 4)
+			};
+			assert!(matches!(
+				reqs[0].args[1..],
+				[WireArgument::Int(x), WireArgument::Int(y)] if (x, y) == xy
+			));
+		}
+		Ok(())
+	}
+
+	#[test]
+	fn damage_buffer_falls_back_to_damage() -> Result<(), WaytinierError> {
+		let (mock, mut app, surface) = surface_app(3)?;
+		let s = surface.borrow();
+		app.handle_requests(s.set_buffer_scale(2))?;
+		app.handle_requests(s.damage_buffer(1, 1, 4, 3))?;
+		app.roundtrip()?;
+
+		let damage = mock.take_requests().into_iter().find(|req| req.name == "damage").unwrap();
+		// pixels 1..5 and 1..4 cover surface units 0..3 and 0..2
+		assert!(matches!(
+			damage.args[..],
+			[
+				WireArgument::Int(0),
+				WireArgument::Int(0),
+				WireArgument::Int(3),
+				WireArgument::Int(2)
+			]
+		));
+		Ok(())
+	}
+
+	#[test]
+	fn events_update_the_surface() -> Result<(), WaytinierError> {
+		let (mock, mut app, surface) = surface_app(6)?;
+		let id = surface.borrow().id.0;
+		mock.send_event(id, 0, vec![WireArgument::Obj(40)])?;
+		mock.send_event(id, 0, vec![WireArgument::Obj(41)])?;
+		mock.send_event(id, 1, vec![WireArgument::Obj(40)])?;
+		mock.send_event(id, 2, vec![WireArgument::Int(2)])?;
+		mock.send_event(id, 3, vec![WireArgument::UnInt(wl_output::Transform::_90 as u32)])?;
+		app.roundtrip()?;
+
+		let s = surface.borrow();
+		assert_eq!(s.outputs(), [41]);
+		assert_eq!(s.preferred_buffer_scale(), 2);
+		assert_eq!(s.preferred_buffer_transform(), Transform::Rotate90);
+		Ok(())
+	}
+
+	#[test]
+	fn regions_are_copied_on_set() -> Result<(), WaytinierError> {
+		let (mock, mut app, surface) = surface_app(6)?;
+		let (pending, region) = app.compositor.borrow().create_region(&mut app.wlim);
+		app.handle_requests(pending)?;
+		let r = region.borrow();
+		app.handle_requests(r.add(0, 0, 4, 4))?;
+		app.handle_requests(r.subtract(1, 1, 2, 2))?;
+		app.handle_requests(surface.borrow().set_opaque_region(Some(&r)))?;
+		app.handle_requests(surface.borrow().set_input_region(None))?;
+		app.handle_requests(r.destroy())?;
+		app.roundtrip()?;
 
-	fn kind(&self) -> super::WaylandObjectKind {
-		todo!()
+		let labels: Vec<_> = mock.take_requests().iter().map(|req| req.label()).collect();
+		let (c, s, r) = (app.compositor.borrow().id, surface.borrow().id, r.id);
+		assert_eq!(
+			labels[..6],
+			[
+				format!("wl_compositor@{c}.create_region"),
+				format!("wl_region@{r}.add"),
+				format!("wl_region@{r}.subtract"),
+				format!("wl_surface@{s}.set_opaque_region"),
+				format!("wl_surface@{s}.set_input_region"),
+				format!("wl_region@{r}.destroy"),
+			]
+		);
+		assert_eq!(mock.object_interface(r.0), None);
+		Ok(())
 	}
 }
@@ -0,0 +1,283 @@
+use std::cell::RefMut;
+
+use crate::{
+	Rl, WaytinierError,
+	wayland::{
+		AppRequest, IdentManager, PixelFormat, WaylandError,
+		shm::{ShmBackend, ShmBuffer, ShmPool, buffer_len},
+	},
+};
+
+// buffers to start out with, one on screen and one to draw into
+const INITIAL_BUFFERS: usize = 2;
+
+// the buffers of one surface, all living in one shm pool. a buffer is busy
+// from the moment it's attached until the compositor releases it, drawing
+// only ever happens into free ones. when all are busy another one is added
+// in the first free space of the pool, which only grows if there's none
+pub(crate) struct Swapchain {
+	pub(crate) pool: Rl<ShmPool>,
+	pub(crate) buffers: Vec<Rl<ShmBuffer>>,
+	// buffers of an old size the compositor still reads from. they get
+	// destroyed once released
+	pub(crate) retired: Vec<Rl<ShmBuffer>>,
+	pub(crate) width: i32,
+	pub(crate) height: i32,
+	pub(crate) pf: PixelFormat,
+}
+
+impl Swapchain {
+	pub(crate) fn new(
+		backend: &ShmBackend,
+		wlim: &mut IdentManager,
+		width: i32,
+		height: i32,
+		pf: PixelFormat,
+	) -> Result<(Vec<AppRequest>, Self), WaytinierError> {
+		let len = frame_len(width, height, pf)?;
+		let (pending, pool) = backend.create_pool(wlim, len * INITIAL_BUFFERS)?;
+		Ok((
+			pending,
+			Self {
+				pool,
+				buffers: vec![],
+				retired: vec![],
+				width,
+				height,
+				pf,
+			},
+		))
+	}
+
+	fn frame_len(&self) -> Result<usize, WaylandError> {
+		frame_len(self.width, self.height, self.pf)
+	}
+
+	fn stride(&self) -> Result<i32, WaylandError> {
+		stride(self.width, self.height, self.pf)
+	}
+
+	// a buffer the compositor isn't reading from. a new one is made if all
+	// of them are busy
+	pub(crate) fn acquire(
+		&mut self,
+		wlim: &mut IdentManager,
+	) -> Result<(Vec<AppRequest>, Rl<ShmBuffer>), WaytinierError> {
+		let mut pending = self.collect_retired();
+		if let Some(buffer) = self.buffers.iter().find(|b| !b.borrow().busy.get()) {
+			return Ok((pending, buffer.clone()));
+		}
+
+		let frame_len = self.frame_len()?;
+		let offset = self.free_offset(frame_len);
+		pending.extend(self.pool.borrow_mut().resize(offset + frame_len)?);
+		let (created, buffer) = self.pool.borrow().create_buffer(
+			wlim,
+			offset,
+			self.width,
+			self.height,
+			self.stride()?,
+			self.pf.wl_shm_format(),
+		)?;
+		pending.extend(created);
+		self.buffers.push(buffer.clone());
+		Ok((pending, buffer))
+	}
+
+	// the first offset with room for len bytes that no buffer of the chain
+	// covers. space of destroyed buffers is used again, busy retired ones
+	// are stepped around
+	fn free_offset(&self, len: usize) -> usize {
+		let mut used: Vec<(usize, usize)> = self
+			.buffers
+			.iter()
+			.chain(&self.retired)
+			.map(|b| (b.borrow().offset, b.borrow().end()))
+			.collect();
+		used.sort_unstable();
+		let mut offset = 0;
+		for (start, end) in used {
+			if start >= offset + len {
+				break;
+			}
+			offset = offset.max(end);
+		}
+		offset
+	}
+
+	// the pixels of a buffer from this chain
+	pub(crate) fn canvas(&self, buffer: &ShmBuffer) -> RefMut<'_, [u8]> {
+		RefMut::map(self.pool.borrow_mut(), |pool| pool.canvas(buffer))
+	}
+
+	// drops every buffer of the old size. free ones go right away, busy ones
+	// once the compositor lets go of them. new buffers never overlap the busy
+	// ones, so nothing on screen gets drawn over
+	pub(crate) fn resize(&mut self, width: i32, height: i32) -> Vec<AppRequest> {
+		if (width, height) == (self.width, self.height) {
+			return vec![];
+		}
+		let mut pending = vec![];
+		for buffer in self.buffers.drain(..) {
+			if buffer.borrow().busy.get() {
+				self.retired.push(buffer);
+			} else {
+				pending.extend(buffer.borrow().destroy());
+			}
+		}
+		self.width = width;
+		self.height = height;
+		pending.extend(self.collect_retired());
+		pending
+	}
+
+	fn collect_retired(&mut self) -> Vec<AppRequest> {
+		let mut pending = vec![];
+		self.retired.retain(|buffer| {
+			let buffer = buffer.borrow();
+			if buffer.busy.get() {
+				return true;
+			}
+			pending.extend(buffer.destroy());
+			false
+		});
+		pending
+	}
+
+	// destroys the buffers and the pool, busy buffers included
+	pub(crate) fn destroy(&mut self) -> Vec<AppRequest> {
+		let mut pending: Vec<AppRequest> = self
+			.buffers
+			.drain(..)
+			.chain(self.retired.drain(..))
+			.flat_map(|b| b.borrow().destroy())
+			.collect();
+		pending.extend(self.pool.borrow().destroy());
+		pending
+	}
+}
+
+fn stride(width: i32, height: i32, pf: PixelFormat) -> Result<i32, WaylandError> {
+	pf.stride(width).ok_or(WaylandError::BadBufferSize(width, height))
+}
+
+fn frame_len(width: i32, height: i32, pf: PixelFormat) -> Result<usize, WaylandError> {
+	buffer_len(width, height, stride(width, height, pf)?)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::{
+		testing::{
+			MockCompositor, WireArgument,
+			fixtures::{TestApp, surface_app},
+		},
+		wayland::surface::Surface,
+	};
+
+	fn setup() -> Result<(MockCompositor, TestApp, Rl<Surface>, Swapchain), WaytinierError> {
+		let (mock, mut app, surface) = surface_app(6)?;
+		let pf = surface.borrow().pf;
+		let (pending, chain) = Swapchain::new(&app.backend, &mut app.wlim, 4, 4, pf)?;
+		app.handle_requests(pending)?;
+		app.roundtrip()?;
+		mock.take_requests();
+		Ok((mock, app, surface, chain))
+	}
+
+	// draws a frame and puts it on the surface
+	fn present(
+		app: &mut TestApp,
+		chain: &mut Swapchain,
+		surface: &Rl<Surface>,
+	) -> Result<Rl<ShmBuffer>, WaytinierError> {
+		let (pending, buffer) = chain.acquire(&mut app.wlim)?;
+		app.handle_requests(pending)?;
+		chain.canvas(&buffer.borrow()).fill(0xff);
+		app.handle_requests(surface.borrow().attach(&buffer.borrow(), 0, 0))?;
+		Ok(buffer)
+	}
+
+	fn release(mock: &MockCompositor, buffer: &Rl<ShmBuffer>) -> Result<(), WaytinierError> {
+		Ok(mock.send_event(buffer.borrow().id.0, 0, vec![])?)
+	}
+
+	#[test]
+	fn grows_when_everything_is_busy() -> Result<(), WaytinierError> {
+		let (mock, mut app, surface, mut chain) = setup()?;
+		let a = present(&mut app, &mut chain, &surface)?;
+		let b = present(&mut app, &mut chain, &surface)?;
+		// both initial buffers are on their way to the compositor
+		let c = present(&mut app, &mut chain, &surface)?;
+		let offsets: Vec<_> = [&a, &b, &c].iter().map(|x| x.borrow().offset).collect();
+		assert_eq!(offsets, [0, 64, 128]);
+		app.roundtrip()?;
+		let resizes: Vec<_> =
+			mock.take_requests().into_iter().filter(|req| req.name == "resize").collect();
+		assert_eq!(resizes.len(), 1);
+		assert!(matches!(resizes[0].args[..], [WireArgument::Int(192)]));
+
+		release(&mock, &a)?;
+		app.roundtrip()?;
+		assert!(!a.borrow().busy.get());
+		let (pending, again) = chain.acquire(&mut app.wlim)?;
+		assert!(pending.is_empty());
+		assert!(Rc::ptr_eq(&a, &again));
+		Ok(())
+	}
+
+	#[test]
+	fn resize_keeps_busy_buffers_alive() -> Result<(), WaytinierError> {
+		let (mock, mut app, surface, mut chain) = setup()?;
+		let shown = present(&mut app, &mut chain, &surface)?;
+		let (pending, unused) = chain.acquire(&mut app.wlim)?;
+		app.handle_requests(pending)?;
+		let (unused_id, shown_id) = (unused.borrow().id.0, shown.borrow().id.0);
+		app.roundtrip()?;
+		assert_eq!(mock.object_interface(unused_id), Some("wl_buffer"));
+
+		let pending = chain.resize(8, 8);
+		app.handle_requests(pending)?;
+		let bigger = present(&mut app, &mut chain, &surface)?;
+		// the old buffer on screen isn't drawn over
+		assert_eq!(bigger.borrow().offset, shown.borrow().end());
+		assert_eq!(bigger.borrow().len(), 8 * 8 * 4);
+		app.roundtrip()?;
+		assert_eq!(mock.object_interface(unused_id), None);
+		assert_eq!(mock.object_interface(shown_id), Some("wl_buffer"));
+
+		release(&mock, &shown)?;
+		app.roundtrip()?;
+		let (pending, _) = chain.acquire(&mut app.wlim)?;
+		app.handle_requests(pending)?;
+		app.roundtrip()?;
+		assert_eq!(mock.object_interface(shown_id), None);
+
+		// a drag resize, each frame shows while the one before is released.
+		// the space of released buffers is reused, so the pool stays at a
+		// few frames of the biggest size
+		let mut shown = bigger;
+		for i in 0..50 {
+			app.handle_requests(chain.resize(6 + i % 3, 8 - i % 2))?;
+			let next = present(&mut app, &mut chain, &surface)?;
+			release(&mock, &shown)?;
+			app.roundtrip()?;
+			shown = next;
+		}
+		assert!(chain.pool.borrow().map.len() <= 3 * 8 * 8 * 4);
+		Ok(())
+	}
+
+	#[test]
+	fn oversized_frames_are_errors() {
+		let pf = PixelFormat::Argb8888;
+		// the row alone overflows the stride
+		let err = frame_len(i32::MAX / 2, 4, pf).unwrap_err();
+		assert!(matches!(err, WaylandError::BadBufferSize(_, 4)));
+		assert!(matches!(frame_len(4, -1, pf), Err(WaylandError::BadBufferSize(4, -1))));
+		assert_eq!(frame_len(4, 4, pf).unwrap(), 64);
+	}
+}
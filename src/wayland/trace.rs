@@ -0,0 +1,158 @@
+// protocol traces in the same format libwayland prints with WAYLAND_DEBUG,
+// so logs of waytinier apps and libwayland apps can be diffed line by line.
+// sent requests are marked with ->, received events aren't. events for
+// objects that were already destroyed are marked discarded
+
+use std::{
+	os::fd::AsRawFd,
+	sync::OnceLock,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::wayland::{
+	Id, IdentManager, OpCode, WaylandObjectKind,
+	wire::{WireArgument, WireRequest},
+};
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+
+// WAYLAND_DEBUG=1 or WAYLAND_DEBUG=client, same check libwayland does
+pub(crate) fn enabled() -> bool {
+	*ENABLED.get_or_init(|| {
+		std::env::var("WAYLAND_DEBUG").is_ok_and(|x| x.contains('1') || x.contains("client"))
+	})
+}
+
+// milliseconds with microsecond precision. libwayland keeps the microseconds
+// in an unsigned int, so they wrap at u32::MAX
+fn timestamp() -> String {
+	let us = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u32;
+	format!("[{:7}.{:03}]", us / 1000, us % 1000)
+}
+
+fn object(kind: Option<WaylandObjectKind>, id: u32) -> String {
+	match kind {
+		Some(kind) => format!("{kind}@{id}"),
+		None => format!("[unknown]@{id}"),
+	}
+}
+
+// creates is the kind behind a typed new_id argument, if the message has one
+fn format_args(
+	args: &[WireArgument],
+	creates: Option<WaylandObjectKind>,
+	wlim: &IdentManager,
+) -> String {
+	let lookup = |id: u32| wlim.kind_of(Id(id));
+	args.iter()
+		.map(|arg| match arg {
+			WireArgument::Int(x) => x.to_string(),
+			WireArgument::UnInt(x) => x.to_string(),
+			WireArgument::FixedPrecision(x) => format!("{:.6}", x.to_f64()),
+			WireArgument::String(Some(x)) => format!("\"{x}\""),
+			WireArgument::String(None) => String::from("nil"),
+			WireArgument::Obj(0) => String::from("nil"),
+			WireArgument::Obj(x) => object(lookup(*x), *x),
+			WireArgument::NewId(x) => format!("new id {}", object(creates, *x)),
+			// libwayland prints the object of an untyped new_id as [unknown],
+			// its interface only shows in the string before it
+			WireArgument::NewIdSpecific(iface, ver, x) => {
+				format!("\"{iface}\", {ver}, new id {}", object(None, *x))
+			}
+			WireArgument::Arr(x) => format!("array[{}]", x.len()),
+			WireArgument::FileDescriptor(x) => format!("fd {}", x.as_raw_fd()),
+		})
+		.collect::<Vec<_>>()
+		.join(", ")
+}
+
+fn line(
+	sent: bool,
+	discarded: bool,
+	kind: WaylandObjectKind,
+	id: Id,
+	name: &str,
+	args: String,
+) -> String {
+	let arrow = if sent {
+		" -> "
+	} else {
+		""
+	};
+	let discarded = if discarded {
+		"discarded "
+	} else {
+		""
+	};
+	format!("{} {discarded}{arrow}{kind}@{id}.{name}({args})", timestamp())
+}
+
+pub(crate) fn request(req: &WireRequest, kind: WaylandObjectKind, wlim: &IdentManager) {
+	let name = kind.request_name(req.opcode).unwrap_or("[unknown]");
+	let args = format_args(&req.args, kind.request_creates(req.opcode), wlim);
+	eprintln!("{}", line(true, false, kind, req.sender_id, name, args));
+}
+
+// discarded is set for events to zombies, nothing handles those
+pub(crate) fn event(
+	id: Id,
+	kind: WaylandObjectKind,
+	opcode: OpCode,
+	args: &[WireArgument],
+	discarded: bool,
+	wlim: &IdentManager,
+) {
+	let name = kind.event_name(opcode).unwrap_or("[unknown]");
+	let args = format_args(args, kind.event_creates(opcode), wlim);
+	eprintln!("{}", line(false, discarded, kind, id, name, args));
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		Fixed,
+		wayland::{display::Display, protocol::wl_registry},
+	};
+
+	#[test]
+	fn libwayland_format() {
+		let mut wlim = IdentManager::default();
+		let display = Display::new_registered(&mut wlim);
+		let display_id = display.borrow().id;
+
+		let req = wl_registry::bind(Id(2), 1, "wl_compositor", 5, Id(3));
+		let args = format_args(&req.args, None, &wlim);
+		assert_eq!(args, "1, \"wl_compositor\", 5, new id [unknown]@3");
+
+		let args = vec![
+			WireArgument::Obj(display_id.0),
+			WireArgument::Obj(0),
+			WireArgument::Obj(9),
+			WireArgument::FixedPrecision(Fixed::from_f64(1.5)),
+			WireArgument::String(None),
+			WireArgument::Arr(vec![0; 8]),
+			WireArgument::Int(-4),
+		];
+		assert_eq!(
+			format_args(&args, None, &wlim),
+			"wl_display@1, nil, [unknown]@9, 1.500000, nil, array[8], -4"
+		);
+
+		let sent = line(
+			true,
+			false,
+			WaylandObjectKind::Display,
+			Id(1),
+			"sync",
+			String::from("new id wl_callback@4"),
+		);
+		let (stamp, rest) = sent.split_once(']').unwrap();
+		assert!(stamp.starts_with('[') && stamp.contains('.'));
+		assert_eq!(rest, "  -> wl_display@1.sync(new id wl_callback@4)");
+
+		let dropped =
+			line(false, true, WaylandObjectKind::Callback, Id(4), "done", String::from("7"));
+		assert_eq!(dropped.split_once(']').unwrap().1, " discarded wl_callback@4.done(7)");
+	}
+}
@@ -1,19 +1,20 @@
 use std::{
 	collections::VecDeque,
 	env,
-	error::Error,
-	fmt::{self, Display},
-	io::{IoSlice, IoSliceMut},
+	ffi::OsString,
+	fmt::Display,
+	io, mem,
 	os::{
-		fd::{FromRawFd, OwnedFd, RawFd},
-		unix::net::{AncillaryData, SocketAncillary, UnixStream},
+		fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+		unix::net::UnixStream,
 	},
 	path::PathBuf,
+	ptr,
 };
 
 use crate::{
-	CYAN, DebugLevel, GREEN, NONE, RED,
-	wayland::{Boxed, Id, OpCode, Raw, WaylandError, WaylandObjectKind},
+	CYAN, DebugLevel, GREEN, NONE, RED, WaytinierError,
+	wayland::{Fixed, Id, IdentManager, OpCode, Raw, WaylandError, WaylandObjectKind, trace},
 	wlog,
 };
 
@@ -27,24 +28,25 @@ pub struct WireEventRaw {
 	pub recv_id: Id,
 	pub opcode: usize,
 	pub payload: Vec<u8>,
+	// the fds this event carries, in the order of its fd arguments
+	pub fds: Vec<OwnedFd>,
 }
 
 #[derive(Debug)]
 pub enum WireArgument {
 	Int(i32),
 	UnInt(u32),
-	// add actual type and helper funs
-	FixedPrecision(u32),
-	String(String),
+	FixedPrecision(Fixed),
+	// None is a null string
+	String(Option<String>),
 	Obj(u32),
 	NewId(u32),
-	NewIdSpecific(&'static str, u32, u32),
+	NewIdSpecific(String, u32, u32),
 	Arr(Vec<u8>),
-	// u32?
-	FileDescriptor(RawFd),
+	FileDescriptor(OwnedFd),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WireArgumentKind {
 	Int,
 	UnInt,
@@ -57,20 +59,37 @@ pub enum WireArgumentKind {
 	FileDescriptor,
 }
 
-pub(crate) enum QueueEntry {
-	EventResponse(WireEventRaw),
-	Request((WireRequest, WaylandObjectKind)),
-	Sync(Id),
-}
+// how much is read from the socket in one go
+const READ_CHUNK: usize = 4096;
+// the most fds libwayland puts in a single sendmsg
+const MAX_FDS_OUT: usize = 28;
+// room for MAX_FDS_OUT fds
+const ANCILLARY_LEN: usize =
+	unsafe { libc::CMSG_SPACE((MAX_FDS_OUT * size_of::<RawFd>()) as u32) } as usize;
+// queued requests are written out on their own once there's this much
+const OUT_FLUSH_THRESHOLD: usize = 4096;
 
 pub(crate) struct MessageManager {
 	pub(crate) sock: UnixStream,
-	pub(crate) q: VecDeque<QueueEntry>,
+	// received bytes, the ones before in_pos were already taken as events
+	pub(crate) in_buf: Vec<u8>,
+	// where the next message starts in in_buf. the taken bytes are only
+	// dropped once per read_socket, not for every message
+	in_pos: usize,
+	// received fds waiting for the event that carries them
+	pub(crate) in_fds: VecDeque<OwnedFd>,
+	// serialized requests waiting for the next flush
+	pub(crate) out_buf: Vec<u8>,
+	// fds to send along, each with the offset of its message in out_buf
+	pub(crate) out_fds: VecDeque<(usize, OwnedFd)>,
 }
 
 impl Drop for MessageManager {
 	fn drop(&mut self) {
 		wlog!(DebugLevel::Important, "wlmm", "destroying self", GREEN, CYAN);
+		if let Err(er) = self.flush() {
+			wlog!(DebugLevel::Error, "wlmm", format!("failed to flush: {er}"), GREEN, RED);
+		}
 		if let Err(er) = self.discon() {
 			wlog!(DebugLevel::Error, "wlmm", format!("failed to discon: {er}"), GREEN, RED);
 		} else {
@@ -120,274 +139,951 @@ impl WireRequest {
 	}
 }
 
+// the display libwayland falls back to when WAYLAND_DISPLAY is unset
+const DEFAULT_DISPLAY: &str = "wayland-0";
+
+fn socket_path(name: &str, runtime_dir: Option<OsString>) -> Result<PathBuf, WaylandError> {
+	let name = PathBuf::from(name);
+	if name.is_absolute() {
+		return Ok(name);
+	}
+	let mut path = PathBuf::from(runtime_dir.ok_or(WaylandError::NoRuntimeDir)?);
+	path.push(name);
+	Ok(path)
+}
+
+// parses WAYLAND_SOCKET and takes ownership of the fd it names
+fn inherited_socket(var: &str) -> Result<OwnedFd, WaylandError> {
+	let bad = || WaylandError::BadWaylandSocket(var.to_string());
+	let fd: RawFd = var.parse().map_err(|_| bad())?;
+	if fd < 0 {
+		return Err(bad());
+	}
+	// F_GETFD fails for fds that aren't open
+	let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+	if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) } < 0 {
+		return Err(bad());
+	}
+	// SAFETY: the fd is open and was handed to this process to own
+	Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
 impl MessageManager {
-	pub fn new(sockname: &str) -> Result<Self, Box<dyn Error>> {
-		let base = env::var("XDG_RUNTIME_DIR")?;
-		let mut base = PathBuf::from(base);
-		base.push(sockname);
-		let sock = UnixStream::connect(base)?;
+	// connects to a socket by name. relative names live in XDG_RUNTIME_DIR,
+	// absolute paths are used as they are
+	pub fn new(sockname: &str) -> Result<Self, WaytinierError> {
+		let path = socket_path(sockname, env::var_os("XDG_RUNTIME_DIR"))?;
+		match UnixStream::connect(&path) {
+			Ok(sock) => Self::from_stream(sock),
+			Err(er) if er.kind() == io::ErrorKind::NotFound => {
+				Err(WaylandError::NoWaylandDisplay(path).into())
+			}
+			Err(er) => Err(er.into()),
+		}
+	}
+
+	// takes over an already connected socket
+	pub fn from_stream(sock: UnixStream) -> Result<Self, WaytinierError> {
 		sock.set_nonblocking(true)?;
 		let wlmm = Self {
 			sock,
-			q: VecDeque::new(),
+			in_buf: Vec::with_capacity(READ_CHUNK),
+			in_pos: 0,
+			in_fds: VecDeque::new(),
+			out_buf: Vec::with_capacity(OUT_FLUSH_THRESHOLD),
+			out_fds: VecDeque::new(),
 		};
 
 		Ok(wlmm)
 	}
 
-	pub fn from_defualt_env() -> Result<Self, Box<dyn Error>> {
-		let env = env::var("WAYLAND_DISPLAY");
-		match env {
-			Ok(x) => Ok(Self::new(&x)?),
-			Err(er) => match er {
-				std::env::VarError::NotPresent => Err(WaylandError::NoWaylandDisplay.boxed()),
-				_ => Err(Box::new(er)),
-			},
+	// same as from_stream, the fd has to be a connected unix socket
+	pub fn from_fd(fd: OwnedFd) -> Result<Self, WaytinierError> {
+		Self::from_stream(UnixStream::from(fd))
+	}
+
+	// the connection rules of libwayland: an fd inherited through
+	// WAYLAND_SOCKET wins, then WAYLAND_DISPLAY, then wayland-0
+	pub fn from_defualt_env() -> Result<Self, WaytinierError> {
+		if let Some(var) = env::var_os("WAYLAND_SOCKET") {
+			let fd = inherited_socket(&var.to_string_lossy())?;
+			// SAFETY: same as libwayland, the variable is dropped so children
+			// don't try to use the fd too. this runs while connecting, before
+			// the app has a reason to read the environment from other threads
+			unsafe { env::remove_var("WAYLAND_SOCKET") };
+			return Self::from_fd(fd);
 		}
+		let name = env::var("WAYLAND_DISPLAY").unwrap_or(String::from(DEFAULT_DISPLAY));
+		Self::new(&name)
 	}
 
-	pub fn discon(&self) -> Result<(), Box<dyn Error>> {
+	pub fn discon(&self) -> Result<(), WaytinierError> {
 		Ok(self.sock.shutdown(std::net::Shutdown::Both)?)
 	}
 
 	pub fn send_request_logged(
-		&self,
-		msg: &mut WireRequest,
-		id: Option<Id>,
-		kind: Option<WaylandObjectKind>,
-		opcode_name: Option<String>,
-	) -> Result<(), Box<dyn Error>> {
-		let dbugmsg = msg.make_debug(id, kind, opcode_name);
+		&mut self,
+		msg: WireRequest,
+		kind: WaylandObjectKind,
+		wlim: &IdentManager,
+	) -> Result<(), WaytinierError> {
+		let opcode_name = kind.request_name(msg.opcode).map(String::from);
+		let dbugmsg = msg.make_debug(Some(msg.sender_id), Some(kind), opcode_name);
 		wlog!(DebugLevel::Trivial, "wlmm", format!("{}", dbugmsg), GREEN, NONE);
+		if trace::enabled() {
+			trace::request(&msg, kind, wlim);
+		}
 		self.send_request(msg)
 	}
 
-	pub fn send_request(&self, msg: &mut WireRequest) -> Result<(), Box<dyn Error>> {
-		let mut buf: Vec<u8> = vec![];
-		buf.append(&mut Vec::from(msg.sender_id.raw().to_ne_bytes()));
-		buf.append(&mut vec![0, 0, 0, 0]);
+	// serializes the request into out_buf. nothing is written to the socket
+	// until flush() unless out_buf grows past OUT_FLUSH_THRESHOLD
+	pub fn send_request(&mut self, msg: WireRequest) -> Result<(), WaytinierError> {
+		let mut body = vec![];
 		let mut fds = vec![];
-		for obj in msg.args.iter_mut() {
+		for obj in msg.args {
 			match obj {
-				WireArgument::Arr(x) => {
-					let len = x.len() as u32;
-					buf.append(&mut Vec::from(len.to_ne_bytes()));
-					buf.append(x);
-					buf.resize(x.len() - (x.len() % 4) - 4, 0);
-				}
-				WireArgument::FileDescriptor(x) => {
-					fds.push(*x);
-				}
-				_ => buf.append(&mut obj.as_vec_u8()),
+				WireArgument::FileDescriptor(x) => fds.push(x),
+				_ => body.append(&mut obj.as_vec_u8()),
 			}
 		}
-		let word2 = (buf.len() << 16) as u32 | (msg.opcode.raw() & 0x0000ffffu32);
-		let word2 = word2.to_ne_bytes();
-		for (en, ix) in (4..=7).enumerate() {
-			buf[ix] = word2[en];
+		// the length is 16 bits in the header, a longer message can't be sent
+		// and nothing of it gets queued
+		let len = body.len() + 8;
+		if len > u16::MAX as usize {
+			return Err(WaylandError::MessageTooLong(len).into());
+		}
+		let start = self.out_buf.len();
+		let word2 = (len as u32) << 16 | (msg.opcode.raw() & 0x0000ffffu32);
+		self.out_buf.extend_from_slice(&msg.sender_id.raw().to_ne_bytes());
+		self.out_buf.extend_from_slice(&word2.to_ne_bytes());
+		self.out_buf.append(&mut body);
+		self.out_fds.extend(fds.into_iter().map(|fd| (start, fd)));
+		wlog!(
+			DebugLevel::SuperVerbose,
+			"wlmm",
+			format!("buf: {:?}", &self.out_buf[start..]),
+			GREEN,
+			NONE
+		);
+		if self.out_buf.len() >= OUT_FLUSH_THRESHOLD {
+			self.flush()?;
 		}
-		let mut ancillary_buf = [0; 128];
-		let mut ancillary = SocketAncillary::new(&mut ancillary_buf);
-		ancillary.add_fds(&fds);
-		wlog!(DebugLevel::SuperVerbose, "wlmm", format!("buf: {buf:?}"), GREEN, NONE);
-		self.sock.send_vectored_with_ancillary(&[IoSlice::new(&buf)], &mut ancillary)?;
 		Ok(())
 	}
 
-	fn get_socket_data(&self, buf: &mut [u8]) -> Result<(usize, Vec<OwnedFd>), Box<dyn Error>> {
-		let mut iov = [IoSliceMut::new(buf)];
+	// writes as much of out_buf as the socket takes. returns true once
+	// everything is out, false if the socket is full and flush has to be
+	// retried later. nothing is lost in that case
+	pub fn flush(&mut self) -> Result<bool, WaytinierError> {
+		while !self.out_buf.is_empty() {
+			let batch = self.out_fds.len().min(MAX_FDS_OUT);
+			// a message can't arrive before its fds, so when not all of them
+			// fit in this sendmsg the bytes stop at the first message left out
+			let limit =
+				self.out_fds.get(MAX_FDS_OUT).map_or(self.out_buf.len(), |(off, _)| *off).max(1);
+			let fds: Vec<RawFd> =
+				self.out_fds.iter().take(batch).map(|(_, fd)| fd.as_raw_fd()).collect();
 
-		let mut aux_buf: [u8; 64] = [0; 64];
-		let mut aux = SocketAncillary::new(&mut aux_buf);
-
-		match self.sock.recv_vectored_with_ancillary(&mut iov, &mut aux) {
-			Ok(l) => {
-				let mut fds = vec![];
-				for msg in aux.messages() {
-					if let Ok(AncillaryData::ScmRights(scmr)) = msg {
-						for fd in scmr {
-							let fd = unsafe { OwnedFd::from_raw_fd(fd) };
-							fds.push(fd);
-						}
+			match send_with_fds(&self.sock, &self.out_buf[..limit], &fds) {
+				Ok(n) => {
+					self.out_buf.drain(..n);
+					self.out_fds.drain(..batch);
+					for (off, _) in self.out_fds.iter_mut() {
+						*off = off.saturating_sub(n);
 					}
 				}
-				Ok((l, fds))
+				Err(er) => {
+					return match er.kind() {
+						std::io::ErrorKind::WouldBlock => Ok(false),
+						_ => Err(er.into()),
+					};
+				}
 			}
-			Err(er) => match er.kind() {
-				std::io::ErrorKind::WouldBlock => Ok((0, vec![])),
-				_ => Err(Box::new(er)),
-			},
 		}
+		Ok(true)
 	}
 
-	pub fn get_events(&mut self) -> Result<(usize, Vec<OwnedFd>), Box<dyn Error>> {
-		let mut b = [0; 8192];
-		let (len, fds) = self.get_socket_data(&mut b)?;
+	// appends whatever the socket has to in_buf and in_fds. returns false once
+	// there's nothing left to read
+	fn get_socket_data(&mut self) -> Result<bool, WaytinierError> {
+		let old_len = self.in_buf.len();
+		self.in_buf.resize(old_len + READ_CHUNK, 0);
+
+		let res = recv_with_fds(&self.sock, &mut self.in_buf[old_len..], &mut self.in_fds);
+		let (len, truncated) = match res {
+			Ok(x) => x,
+			Err(er) => {
+				self.in_buf.truncate(old_len);
+				return match er.kind() {
+					std::io::ErrorKind::WouldBlock => Ok(false),
+					_ => Err(er.into()),
+				};
+			}
+		};
+		self.in_buf.truncate(old_len + len);
+		if truncated {
+			return Err(WaylandError::AncillaryTruncated.into());
+		}
 		if len == 0 {
-			return Ok((0, vec![]));
+			return Err(WaylandError::ConnectionClosed.into());
 		}
+		Ok(true)
+	}
 
-		let mut cursor = 0;
-		let mut ctr = 0;
-		while cursor < len {
-			let sender_id =
-				u32::from_ne_bytes([b[cursor], b[cursor + 1], b[cursor + 2], b[cursor + 3]]);
-			let byte2 =
-				u32::from_ne_bytes([b[cursor + 4], b[cursor + 5], b[cursor + 6], b[cursor + 7]]);
+	// blocks until there's something to read, or until the socket takes
+	// writes again if out_buf still holds data. a timeout of -1 waits forever,
+	// false means it ran out
+	pub fn wait(&self, timeout_ms: i32) -> Result<bool, WaytinierError> {
+		let mut events = libc::POLLIN;
+		if !self.out_buf.is_empty() {
+			events |= libc::POLLOUT;
+		}
+		Ok(poll_fd(&self.sock, events, timeout_ms)?)
+	}
 
-			let recv_len = byte2 >> 16;
-			// println!("len: {}", recv_len);
-			if recv_len < 8 {
-				return Err(WaylandError::RecvLenBad.boxed());
+	// reads everything the socket has right now, returns the amount of bytes.
+	// a hangup is only reported by a read that got nothing new, the bytes
+	// before it still get dispatched. usually that's the wl_display.error
+	// saying why
+	pub fn read_socket(&mut self) -> Result<usize, WaytinierError> {
+		self.in_buf.drain(..self.in_pos);
+		self.in_pos = 0;
+		let before = self.in_buf.len();
+		loop {
+			match self.get_socket_data() {
+				Ok(true) => (),
+				Ok(false) => break,
+				Err(WaytinierError::Wayland(WaylandError::ConnectionClosed))
+					if self.in_buf.len() > before =>
+				{
+					break;
+				}
+				Err(er) => return Err(er),
 			}
-			let opcode = (byte2 & 0x0000ffff) as usize;
+		}
+		Ok(self.in_buf.len() - before)
+	}
 
-			let payload = Vec::from(&b[cursor + 8..cursor + recv_len as usize]);
+	// takes the next complete message out of in_buf, together with the fds its
+	// signature asks for. messages are framed one at a time so that objects
+	// created by an earlier event are known by the time their events arrive
+	pub fn next_event(
+		&mut self,
+		wlim: &IdentManager,
+	) -> Result<Option<WireEventRaw>, WaytinierError> {
+		let b = &self.in_buf[self.in_pos..];
+		if b.len() < 8 {
+			return Ok(None);
+		}
+		let sender_id = u32::from_ne_bytes([b[0], b[1], b[2], b[3]]);
+		let byte2 = u32::from_ne_bytes([b[4], b[5], b[6], b[7]]);
 
-			let event = WireEventRaw {
-				recv_id: Id(sender_id),
-				opcode,
-				payload,
-			};
-			self.q.push_back(QueueEntry::EventResponse(event));
-			ctr += 1;
+		let recv_len = (byte2 >> 16) as usize;
+		// every message is made of whole 4 byte words
+		if recv_len < 8 || !recv_len.is_multiple_of(4) {
+			return Err(WaylandError::RecvLenBad.into());
+		}
+		if b.len() < recv_len {
+			return Ok(None);
+		}
+		let opcode = (byte2 & 0x0000ffff) as usize;
+
+		// objects we don't know can't be decoded either, so no fds are taken
+		// for them
+		let fd_count = wlim
+			.kind_of(Id(sender_id))
+			.and_then(|kind| kind.event_signature(OpCode(opcode as u32)))
+			.map_or(0, |sig| {
+				sig.iter().filter(|k| **k == WireArgumentKind::FileDescriptor).count()
+			});
+		// fds are sent along with the first byte of their message, so they're
+		// always here by the time the message is complete
+		if self.in_fds.len() < fd_count {
+			return Err(WaylandError::FdExpected.into());
+		}
+		let fds = self.in_fds.drain(..fd_count).collect();
+		let payload = b[8..recv_len].to_vec();
+		self.in_pos += recv_len;
+
+		Ok(Some(WireEventRaw {
+			recv_id: Id(sender_id),
+			opcode,
+			payload,
+			fds,
+		}))
+	}
+}
+
+// control message storage, aligned like the headers that get written in it
+#[repr(C)]
+struct CmsgBuffer {
+	_align: [libc::cmsghdr; 0],
+	buf: [u8; ANCILLARY_LEN],
+}
+
+impl CmsgBuffer {
+	fn new() -> Self {
+		Self {
+			_align: [],
+			buf: [0; ANCILLARY_LEN],
+		}
+	}
+}
+
+// sendmsg with the fds attached as SCM_RIGHTS
+pub(crate) fn send_with_fds(sock: &impl AsRawFd, data: &[u8], fds: &[RawFd]) -> io::Result<usize> {
+	let mut iov = libc::iovec {
+		iov_base: data.as_ptr() as *mut libc::c_void,
+		iov_len: data.len(),
+	};
+	let mut cmsg = CmsgBuffer::new();
+	let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+	msg.msg_iov = &mut iov;
+	msg.msg_iovlen = 1;
 
-			cursor += recv_len as usize;
+	if !fds.is_empty() {
+		let fds_len = size_of_val(fds);
+		let space = unsafe { libc::CMSG_SPACE(fds_len as u32) } as usize;
+		if space > ANCILLARY_LEN {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"too many fds for one message",
+			));
+		}
+		msg.msg_control = cmsg.buf.as_mut_ptr().cast();
+		msg.msg_controllen = space as _;
+		// SAFETY: msg_control points at space bytes of aligned storage, which
+		// is enough for one header followed by the fds
+		unsafe {
+			let hdr = libc::CMSG_FIRSTHDR(&msg);
+			(*hdr).cmsg_level = libc::SOL_SOCKET;
+			(*hdr).cmsg_type = libc::SCM_RIGHTS;
+			(*hdr).cmsg_len = libc::CMSG_LEN(fds_len as u32) as _;
+			ptr::copy_nonoverlapping(fds.as_ptr().cast::<u8>(), libc::CMSG_DATA(hdr), fds_len);
 		}
-		Ok((ctr, fds))
 	}
 
-	pub fn queue_request(&mut self, req: WireRequest, kind: WaylandObjectKind) {
-		self.q.push_back(QueueEntry::Request((req, kind)));
+	let ret =
+		unsafe { libc::sendmsg(sock.as_raw_fd(), &msg, libc::MSG_NOSIGNAL | libc::MSG_DONTWAIT) };
+	if ret < 0 {
+		Err(io::Error::last_os_error())
+	} else {
+		Ok(ret as usize)
+	}
+}
+
+// recvmsg, received fds are pushed to the queue with CLOEXEC already set.
+// returns the amount of bytes read and whether control data was cut off
+pub(crate) fn recv_with_fds(
+	sock: &impl AsRawFd,
+	data: &mut [u8],
+	fds: &mut VecDeque<OwnedFd>,
+) -> io::Result<(usize, bool)> {
+	let mut iov = libc::iovec {
+		iov_base: data.as_mut_ptr().cast(),
+		iov_len: data.len(),
+	};
+	let mut cmsg = CmsgBuffer::new();
+	let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+	msg.msg_iov = &mut iov;
+	msg.msg_iovlen = 1;
+	msg.msg_control = cmsg.buf.as_mut_ptr().cast();
+	msg.msg_controllen = ANCILLARY_LEN as _;
+
+	let ret = unsafe {
+		libc::recvmsg(sock.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC | libc::MSG_DONTWAIT)
+	};
+	if ret < 0 {
+		return Err(io::Error::last_os_error());
+	}
+
+	// SAFETY: the kernel filled in msg_control and msg_controllen, the cmsg
+	// macros stay within those bounds
+	unsafe {
+		let mut hdr = libc::CMSG_FIRSTHDR(&msg);
+		while !hdr.is_null() {
+			if (*hdr).cmsg_level == libc::SOL_SOCKET && (*hdr).cmsg_type == libc::SCM_RIGHTS {
+				let data_len = (*hdr).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+				let data = libc::CMSG_DATA(hdr).cast::<RawFd>();
+				for ix in 0..data_len / size_of::<RawFd>() {
+					fds.push_back(OwnedFd::from_raw_fd(data.add(ix).read_unaligned()));
+				}
+			}
+			hdr = libc::CMSG_NXTHDR(&msg, hdr);
+		}
+	}
+	Ok((ret as usize, msg.msg_flags & libc::MSG_CTRUNC != 0))
+}
+
+// poll(2) on a single fd, retried when interrupted. false on timeout
+pub(crate) fn poll_fd(
+	fd: &impl AsRawFd,
+	events: libc::c_short,
+	timeout_ms: i32,
+) -> io::Result<bool> {
+	let mut pfd = libc::pollfd {
+		fd: fd.as_raw_fd(),
+		events,
+		revents: 0,
+	};
+	loop {
+		match unsafe { libc::poll(&mut pfd, 1, timeout_ms) } {
+			-1 => {
+				let er = io::Error::last_os_error();
+				if er.kind() != io::ErrorKind::Interrupted {
+					return Err(er);
+				}
+			}
+			0 => return Ok(false),
+			_ => return Ok(true),
+		}
 	}
 }
 
 impl WireArgument {
-	// size in bytes
+	pub fn kind(&self) -> WireArgumentKind {
+		match self {
+			WireArgument::Int(_) => WireArgumentKind::Int,
+			WireArgument::UnInt(_) => WireArgumentKind::UnInt,
+			WireArgument::FixedPrecision(_) => WireArgumentKind::FixedPrecision,
+			WireArgument::String(_) => WireArgumentKind::String,
+			WireArgument::Obj(_) => WireArgumentKind::Obj,
+			WireArgument::NewId(_) => WireArgumentKind::NewId,
+			WireArgument::NewIdSpecific(..) => WireArgumentKind::NewIdSpecific,
+			WireArgument::Arr(_) => WireArgumentKind::Arr,
+			WireArgument::FileDescriptor(_) => WireArgumentKind::FileDescriptor,
+		}
+	}
+
+	// bytes the argument takes up in the message, fds travel out of band
 	pub fn size(&self) -> usize {
 		match self {
 			WireArgument::Int(_) => 4,
 			WireArgument::UnInt(_) => 4,
 			WireArgument::FixedPrecision(_) => 4,
-			WireArgument::String(x) => x.len(),
+			WireArgument::String(None) => 4,
+			WireArgument::String(Some(x)) => sized_len(x.len() + 1),
 			WireArgument::Obj(_) => 4,
 			WireArgument::NewId(_) => 4,
-			WireArgument::NewIdSpecific(x, _, _) => x.len() + 8,
-			WireArgument::Arr(x) => x.len(),
-			WireArgument::FileDescriptor(_) => 4,
+			WireArgument::NewIdSpecific(x, _, _) => sized_len(x.len() + 1) + 8,
+			WireArgument::Arr(x) => sized_len(x.len()),
+			WireArgument::FileDescriptor(_) => 0,
 		}
 	}
 
 	pub fn as_vec_u8(&self) -> Vec<u8> {
+		let mut complete = Vec::with_capacity(self.size());
 		match self {
-			WireArgument::Int(x) => Vec::from(x.to_ne_bytes()),
-			WireArgument::UnInt(x) => Vec::from(x.to_ne_bytes()),
-			WireArgument::FixedPrecision(x) => Vec::from(x.to_ne_bytes()),
-			WireArgument::String(x) => {
-				let mut complete: Vec<u8> = vec![];
-				// str len + 1 because of nul
-				let len = &mut Vec::from(((x.len() + 1) as u32).to_ne_bytes());
-				complete.append(len);
-				complete.append(&mut Vec::from(x.as_str()));
-				// nul
-				complete.push(0);
-				// padding
-				complete.resize(complete.len() - (complete.len() % 4) + 4, 0);
-				// println!("complete len rn: {}", complete.len());
-				complete
-			}
-			WireArgument::Obj(x) => Vec::from(x.to_ne_bytes()),
-			WireArgument::NewId(x) => Vec::from(x.to_ne_bytes()),
+			WireArgument::Int(x) => complete.extend_from_slice(&x.to_ne_bytes()),
+			WireArgument::UnInt(x) => complete.extend_from_slice(&x.to_ne_bytes()),
+			WireArgument::FixedPrecision(x) => complete.extend_from_slice(&x.raw().to_ne_bytes()),
+			WireArgument::String(None) => complete.extend_from_slice(&[0, 0, 0, 0]),
+			WireArgument::String(Some(x)) => put_string(&mut complete, x),
+			WireArgument::Obj(x) => complete.extend_from_slice(&x.to_ne_bytes()),
+			WireArgument::NewId(x) => complete.extend_from_slice(&x.to_ne_bytes()),
 			WireArgument::NewIdSpecific(x, y, z) => {
-				let mut complete: Vec<u8> = vec![];
-				// str len
-				let len = &mut Vec::from(((x.len() + 1) as u32).to_ne_bytes());
-				complete.append(len);
-				// println!("len: {}, complete: {:?}", complete.len(), complete);
-				complete.append(&mut Vec::from(*x));
-				// println!("len: {}, complete: {:?}", complete.len(), complete);
-				complete.push(0);
-				// println!("len: {}, complete: {:?}", complete.len(), complete);
-				// pad str
-				let clen = complete.len();
-				complete.resize(clen - (clen % 4) + (4 * (clen % 4).clamp(0, 1)), 0);
-				// println!("len: {}, complete: {:?}", complete.len(), complete);
-				complete.append(&mut Vec::from(y.to_ne_bytes()));
-				// println!("len: {}, complete: {:?}", complete.len(), complete);
-				complete.append(&mut Vec::from(z.to_ne_bytes()));
-				// println!("len: {}, complete: {:?}", complete.len(), complete);
-				// println!("complete len rn: {}", complete.len());
-				complete
+				put_string(&mut complete, x);
+				complete.extend_from_slice(&y.to_ne_bytes());
+				complete.extend_from_slice(&z.to_ne_bytes());
 			}
-			WireArgument::Arr(_) => panic!("debil"),
-			WireArgument::FileDescriptor(x) => Vec::from(x.to_ne_bytes()),
+			WireArgument::Arr(x) => put_sized(&mut complete, x),
+			// sent as ancillary data, nothing goes in the message itself
+			WireArgument::FileDescriptor(_) => (),
 		}
+		complete
 	}
 }
 
-pub(crate) trait FromWirePayload: Sized {
-	fn from_wire(payload: &[u8]) -> Result<Self, Box<dyn Error>>;
+// length prefix plus the data padded to 4 bytes
+fn sized_len(len: usize) -> usize {
+	4 + len.next_multiple_of(4)
 }
 
-fn is_empty(payload: &[u8]) -> Result<(), Box<dyn Error>> {
-	if payload.is_empty() {
-		Err(WaylandError::EmptyFromWirePayload.boxed())
-	} else {
-		Ok(())
+// writes the length, the data and then zeroes up to the next 4 byte boundary
+fn put_sized(buf: &mut Vec<u8>, data: &[u8]) {
+	buf.extend_from_slice(&(data.len() as u32).to_ne_bytes());
+	buf.extend_from_slice(data);
+	buf.resize(buf.len() + data.len().next_multiple_of(4) - data.len(), 0);
+}
+
+// strings carry their nul in the length
+fn put_string(buf: &mut Vec<u8>, s: &str) {
+	buf.extend_from_slice(&((s.len() + 1) as u32).to_ne_bytes());
+	buf.extend_from_slice(s.as_bytes());
+	buf.push(0);
+	buf.resize(buf.len() + (s.len() + 1).next_multiple_of(4) - (s.len() + 1), 0);
+}
+
+// walks a payload front to back, keeping track of how much was consumed
+struct WireReader<'a> {
+	payload: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> WireReader<'a> {
+	fn take(&mut self, len: usize) -> Result<&'a [u8], WaylandError> {
+		let end = self.pos.checked_add(len).ok_or(WaylandError::RecvLenBad)?;
+		let bytes = self.payload.get(self.pos..end).ok_or(WaylandError::RecvLenBad)?;
+		self.pos = end;
+		Ok(bytes)
+	}
+
+	fn u32(&mut self) -> Result<u32, WaylandError> {
+		let b = self.take(4)?;
+		Ok(u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+	}
+
+	// length prefixed data, the padding up to 4 bytes is skipped
+	fn sized(&mut self) -> Result<&'a [u8], WaylandError> {
+		let len = self.u32()? as usize;
+		let data = self.take(len)?;
+		self.take(len.next_multiple_of(4) - len)?;
+		Ok(data)
+	}
+
+	fn string(&mut self) -> Result<Option<String>, WaytinierError> {
+		let data = self.sized()?;
+		match data.split_last() {
+			// zero length means a null string
+			None => Ok(None),
+			Some((0, s)) => Ok(Some(String::from_utf8(s.to_vec())?)),
+			Some(_) => Err(WaylandError::StringNotTerminated.into()),
+		}
+	}
+}
+
+// decodes a whole event payload following its signature. fds are taken in
+// the order the fd arguments appear
+pub(crate) fn decode_args(
+	payload: &[u8],
+	signature: &[WireArgumentKind],
+	fds: &mut impl Iterator<Item = OwnedFd>,
+) -> Result<Vec<WireArgument>, WaytinierError> {
+	let mut rd = WireReader {
+		payload,
+		pos: 0,
+	};
+	let mut args = Vec::with_capacity(signature.len());
+	for kind in signature {
+		let arg = match kind {
+			WireArgumentKind::Int => WireArgument::Int(rd.u32()? as i32),
+			WireArgumentKind::UnInt => WireArgument::UnInt(rd.u32()?),
+			WireArgumentKind::FixedPrecision => {
+				WireArgument::FixedPrecision(Fixed::from_raw(rd.u32()? as i32))
+			}
+			WireArgumentKind::String => WireArgument::String(rd.string()?),
+			WireArgumentKind::Obj => WireArgument::Obj(rd.u32()?),
+			WireArgumentKind::NewId => WireArgument::NewId(rd.u32()?),
+			WireArgumentKind::NewIdSpecific => {
+				let interface = rd.string()?.ok_or(WaylandError::StringNotTerminated)?;
+				WireArgument::NewIdSpecific(interface, rd.u32()?, rd.u32()?)
+			}
+			WireArgumentKind::Arr => WireArgument::Arr(rd.sized()?.to_vec()),
+			WireArgumentKind::FileDescriptor => {
+				WireArgument::FileDescriptor(fds.next().ok_or(WaylandError::FdExpected)?)
+			}
+		};
+		args.push(arg);
+	}
+	if rd.pos != payload.len() {
+		return Err(WaylandError::RecvLenBad.into());
 	}
+	Ok(args)
 }
 
-impl FromWirePayload for String {
-	fn from_wire(payload: &[u8]) -> Result<Self, Box<dyn Error>> {
-		is_empty(payload)?;
-		let p = payload;
-		let len = u32::from_wire(payload)? as usize;
-		let ix = p[4..4 + len]
-			.iter()
-			.enumerate()
-			.find(|(_, c)| **c == b'\0')
-			.map(|(e, _)| e)
-			.unwrap_or_default();
-		Ok(String::from_utf8(p[4..4 + ix].to_vec())?)
+// typed access to a decoded argument, used by the generated event decoders
+pub(crate) trait FromWireArgument: Sized {
+	fn from_arg(arg: WireArgument) -> Result<Self, WaylandError>;
+}
+
+impl FromWireArgument for i32 {
+	fn from_arg(arg: WireArgument) -> Result<Self, WaylandError> {
+		match arg {
+			WireArgument::Int(x) => Ok(x),
+			other => Err(WaylandError::ArgumentMismatch(WireArgumentKind::Int, other.kind())),
+		}
 	}
 }
 
-impl FromWirePayload for u32 {
-	fn from_wire(payload: &[u8]) -> Result<Self, Box<dyn Error>> {
-		is_empty(payload)?;
-		let p = payload;
-		Ok(u32::from_ne_bytes([p[0], p[1], p[2], p[3]]))
+impl FromWireArgument for u32 {
+	fn from_arg(arg: WireArgument) -> Result<Self, WaylandError> {
+		match arg {
+			WireArgument::UnInt(x) => Ok(x),
+			other => Err(WaylandError::ArgumentMismatch(WireArgumentKind::UnInt, other.kind())),
+		}
 	}
 }
 
-impl FromWirePayload for i32 {
-	fn from_wire(payload: &[u8]) -> Result<Self, Box<dyn Error>> {
-		is_empty(payload)?;
-		let p = payload;
-		Ok(i32::from_ne_bytes([p[0], p[1], p[2], p[3]]))
+impl FromWireArgument for Fixed {
+	fn from_arg(arg: WireArgument) -> Result<Self, WaylandError> {
+		match arg {
+			WireArgument::FixedPrecision(x) => Ok(x),
+			other => {
+				Err(WaylandError::ArgumentMismatch(WireArgumentKind::FixedPrecision, other.kind()))
+			}
+		}
 	}
 }
 
-impl FromWirePayload for Vec<u32> {
-	fn from_wire(payload: &[u8]) -> Result<Self, Box<dyn Error>> {
-		is_empty(payload)?;
-		payload[4..].chunks(4).map(|chunk| u32::from_wire(chunk)).collect()
+impl FromWireArgument for Option<String> {
+	fn from_arg(arg: WireArgument) -> Result<Self, WaylandError> {
+		match arg {
+			WireArgument::String(x) => Ok(x),
+			other => Err(WaylandError::ArgumentMismatch(WireArgumentKind::String, other.kind())),
+		}
 	}
 }
 
-#[derive(Debug)]
-pub(crate) struct RecvError {
-	pub(crate) recv_id: Id,
-	pub(crate) id: Id,
-	pub(crate) code: OpCode,
-	pub(crate) msg: String,
+impl FromWireArgument for String {
+	fn from_arg(arg: WireArgument) -> Result<Self, WaylandError> {
+		Option::<String>::from_arg(arg)?.ok_or(WaylandError::UnexpectedNull)
+	}
+}
+
+impl FromWireArgument for Option<Id> {
+	fn from_arg(arg: WireArgument) -> Result<Self, WaylandError> {
+		match arg {
+			WireArgument::Obj(0) | WireArgument::NewId(0) => Ok(None),
+			WireArgument::Obj(x) | WireArgument::NewId(x) => Ok(Some(Id(x))),
+			other => Err(WaylandError::ArgumentMismatch(WireArgumentKind::Obj, other.kind())),
+		}
+	}
+}
+
+impl FromWireArgument for Id {
+	fn from_arg(arg: WireArgument) -> Result<Self, WaylandError> {
+		Option::<Id>::from_arg(arg)?.ok_or(WaylandError::UnexpectedNull)
+	}
+}
+
+impl FromWireArgument for Vec<u8> {
+	fn from_arg(arg: WireArgument) -> Result<Self, WaylandError> {
+		match arg {
+			WireArgument::Arr(x) => Ok(x),
+			other => Err(WaylandError::ArgumentMismatch(WireArgumentKind::Arr, other.kind())),
+		}
+	}
+}
+
+impl FromWireArgument for OwnedFd {
+	fn from_arg(arg: WireArgument) -> Result<Self, WaylandError> {
+		match arg {
+			WireArgument::FileDescriptor(x) => Ok(x),
+			other => {
+				Err(WaylandError::ArgumentMismatch(WireArgumentKind::FileDescriptor, other.kind()))
+			}
+		}
+	}
 }
 
-impl Boxed for RecvError {}
-impl Error for RecvError {}
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn socket_paths() {
+		let runtime = Some(OsString::from("/run/user/1000"));
+		assert_eq!(
+			socket_path("wayland-1", runtime.clone()).unwrap(),
+			PathBuf::from("/run/user/1000/wayland-1")
+		);
+		// absolute paths don't need XDG_RUNTIME_DIR at all
+		assert_eq!(socket_path("/tmp/wl-sock", None).unwrap(), PathBuf::from("/tmp/wl-sock"));
+		assert!(matches!(socket_path("wayland-0", None), Err(WaylandError::NoRuntimeDir)));
+	}
+
+	#[test]
+	fn wayland_socket_fd() {
+		use std::os::fd::IntoRawFd;
+
+		let (ours, _theirs) = UnixStream::pair().unwrap();
+		let raw = OwnedFd::from(ours).into_raw_fd();
+		let fd = inherited_socket(&raw.to_string()).unwrap();
+		assert_eq!(fd.as_raw_fd(), raw);
+		let flags = unsafe { libc::fcntl(raw, libc::F_GETFD) };
+		assert_ne!(flags & libc::FD_CLOEXEC, 0);
+		assert!(MessageManager::from_fd(fd).is_ok());
+
+		assert!(inherited_socket("nope").is_err());
+		assert!(inherited_socket("-3").is_err());
+	}
+
+	fn roundtrip(arg: WireArgument, kind: WireArgumentKind) -> WireArgument {
+		let bytes = arg.as_vec_u8();
+		assert_eq!(bytes.len(), arg.size());
+		assert_eq!(bytes.len() % 4, 0);
+		let mut args = decode_args(&bytes, &[kind], &mut std::iter::empty()).unwrap();
+		assert_eq!(args.len(), 1);
+		args.pop().unwrap()
+	}
+
+	#[test]
+	fn string_padding() {
+		// length, nul included, and the padded size of the whole argument
+		for (s, wire_len, total) in
+			[("", 1, 8), ("a", 2, 8), ("abc", 4, 8), ("abcd", 5, 12), ("abcde", 6, 12)]
+		{
+			let arg = WireArgument::String(Some(String::from(s)));
+			let bytes = arg.as_vec_u8();
+			assert_eq!(bytes.len(), total, "{s:?}");
+			assert_eq!(u32::from_ne_bytes(bytes[..4].try_into().unwrap()), wire_len);
+			assert!(bytes[4 + s.len()..].iter().all(|b| *b == 0));
+			match roundtrip(arg, WireArgumentKind::String) {
+				WireArgument::String(Some(x)) => assert_eq!(x, s),
+				other => panic!("{other:?}"),
+			}
+		}
+	}
+
+	#[test]
+	fn null_string() {
+		let arg = WireArgument::String(None);
+		assert_eq!(arg.as_vec_u8(), [0, 0, 0, 0]);
+		assert!(matches!(roundtrip(arg, WireArgumentKind::String), WireArgument::String(None)));
+	}
+
+	#[test]
+	fn array_padding() {
+		for (len, total) in [(0, 4), (1, 8), (3, 8), (4, 8), (5, 12)] {
+			let data: Vec<u8> = (1..=len as u8).collect();
+			let arg = WireArgument::Arr(data.clone());
+			let bytes = arg.as_vec_u8();
+			assert_eq!(bytes.len(), total, "len {len}");
+			assert_eq!(u32::from_ne_bytes(bytes[..4].try_into().unwrap()), len);
+			assert_eq!(&bytes[4..4 + len as usize], data.as_slice());
+			assert!(bytes[4 + len as usize..].iter().all(|b| *b == 0));
+			match roundtrip(arg, WireArgumentKind::Arr) {
+				WireArgument::Arr(x) => assert_eq!(x, data),
+				other => panic!("{other:?}"),
+			}
+		}
+	}
+
+	#[test]
+	fn new_id_with_interface() {
+		let arg = WireArgument::NewIdSpecific(String::from("wl_seat"), 7, 12);
+		// "wl_seat" + nul is exactly 8 bytes, no padding
+		assert_eq!(arg.as_vec_u8().len(), 4 + 8 + 4 + 4);
+		match roundtrip(arg, WireArgumentKind::NewIdSpecific) {
+			WireArgument::NewIdSpecific(iface, ver, id) => {
+				assert_eq!((iface.as_str(), ver, id), ("wl_seat", 7, 12));
+			}
+			other => panic!("{other:?}"),
+		}
+	}
+
+	#[test]
+	fn args_after_padding() {
+		// a padded array followed by a uint has to line up again
+		let mut bytes = WireArgument::Arr(vec![9; 5]).as_vec_u8();
+		bytes.extend(WireArgument::UnInt(42).as_vec_u8());
+		let args = decode_args(
+			&bytes,
+			&[WireArgumentKind::Arr, WireArgumentKind::UnInt],
+			&mut std::iter::empty(),
+		)
+		.unwrap();
+		assert!(matches!(args[1], WireArgument::UnInt(42)));
+	}
+
+	#[test]
+	fn events_split_across_reads() -> Result<(), WaytinierError> {
+		use std::io::Write;
+
+		use crate::wayland::{callback::Callback, display::Display};
 
-impl Display for RecvError {
-	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-		write!(f, "id: {}, code: {}\nmsg: {}", self.id, self.code, self.msg)
+		let (mut server, client) = UnixStream::pair()?;
+		let mut wlmm = MessageManager::from_stream(client)?;
+		let mut wlim = IdentManager::default();
+		Display::new_registered(&mut wlim);
+		let cb = Callback::new_registered(&mut wlim);
+		let id = cb.borrow().id.raw();
+		// wl_callback.done, 12 bytes each
+		let done = |data: u32| [id, 12 << 16, data].map(u32::to_ne_bytes).concat();
+
+		let mut stream: Vec<u8> = (0..100).flat_map(done).collect();
+		stream.extend_from_slice(&done(100)[..6]);
+		server.write_all(&stream)?;
+		wlmm.read_socket()?;
+		for data in 0..100u32 {
+			let event = wlmm.next_event(&wlim)?.unwrap();
+			assert_eq!(event.payload, data.to_ne_bytes());
+		}
+		assert!(wlmm.next_event(&wlim)?.is_none());
+		// nothing was moved for the taken events yet
+		assert_eq!(wlmm.in_buf.len(), stream.len());
+
+		server.write_all(&done(100)[6..])?;
+		wlmm.read_socket()?;
+		assert_eq!(wlmm.in_buf.len(), 12);
+		let event = wlmm.next_event(&wlim)?.unwrap();
+		assert_eq!(event.payload, 100u32.to_ne_bytes());
+		assert!(wlmm.next_event(&wlim)?.is_none());
+		Ok(())
+	}
+
+	#[test]
+	fn messages_over_the_length_limit_are_refused() -> Result<(), WaytinierError> {
+		let (_server, client) = UnixStream::pair()?;
+		let mut wlmm = MessageManager::from_stream(client)?;
+		let request = |len, fd| WireRequest {
+			sender_id: Id(3),
+			opcode: OpCode(0),
+			args: vec![WireArgument::Arr(vec![0; len]), WireArgument::FileDescriptor(fd)],
+		};
+		let fd = || OwnedFd::from(UnixStream::pair().unwrap().0);
+
+		// header and array length make it one word too long
+		let er = wlmm.send_request(request(65524, fd())).unwrap_err();
+		assert!(matches!(er, WaytinierError::Wayland(WaylandError::MessageTooLong(65536))));
+		assert!(wlmm.out_buf.is_empty());
+		assert!(wlmm.out_fds.is_empty());
+
+		// the longest message that fits
+		wlmm.send_request(request(65520, fd()))?;
+		Ok(())
+	}
+
+	#[test]
+	fn truncated_array() {
+		let mut bytes = WireArgument::Arr(vec![1, 2, 3, 4, 5]).as_vec_u8();
+		bytes.truncate(8);
+		assert!(decode_args(&bytes, &[WireArgumentKind::Arr], &mut std::iter::empty()).is_err());
+	}
+
+	// xorshift, good enough to throw garbage at the parser reproducibly
+	struct Rng(u64);
+
+	impl Rng {
+		fn next(&mut self) -> u32 {
+			self.0 ^= self.0 << 13;
+			self.0 ^= self.0 >> 7;
+			self.0 ^= self.0 << 17;
+			(self.0 >> 32) as u32
+		}
+
+		fn below(&mut self, n: u32) -> u32 {
+			self.next() % n
+		}
+
+		// mostly small words, so lengths and ids are plausible often enough
+		// to get past the first check
+		fn word(&mut self) -> u32 {
+			match self.below(4) {
+				0 => self.next(),
+				1 => u32::from_ne_bytes([b'w', b'l', b'_', self.below(2) as u8]),
+				_ => self.below(24),
+			}
+		}
+
+		// up to max words
+		fn words(&mut self, max: u32) -> Vec<u8> {
+			(0..self.below(max)).flat_map(|_| self.word().to_ne_bytes()).collect()
+		}
+
+		// a well formed argument, fds aside
+		fn arg(&mut self, kind: WireArgumentKind) -> Vec<u8> {
+			let text = |rng: &mut Self| (0..rng.below(12)).map(|_| 'a').collect::<String>();
+			match kind {
+				WireArgumentKind::String if self.below(4) == 0 => {
+					WireArgument::String(None).as_vec_u8()
+				}
+				WireArgumentKind::String | WireArgumentKind::NewIdSpecific => {
+					WireArgument::String(Some(text(self))).as_vec_u8()
+				}
+				WireArgumentKind::Arr => WireArgument::Arr(self.words(4)).as_vec_u8(),
+				WireArgumentKind::FileDescriptor => vec![],
+				_ => self.word().to_ne_bytes().to_vec(),
+			}
+		}
+	}
+
+	const KINDS: [WireArgumentKind; 9] = [
+		WireArgumentKind::Int,
+		WireArgumentKind::UnInt,
+		WireArgumentKind::FixedPrecision,
+		WireArgumentKind::String,
+		WireArgumentKind::Obj,
+		WireArgumentKind::NewId,
+		WireArgumentKind::NewIdSpecific,
+		WireArgumentKind::Arr,
+		WireArgumentKind::FileDescriptor,
+	];
+
+	#[test]
+	fn fuzz_decode_args() {
+		let mut rng = Rng(0x5eed_cafe_f00d_0001);
+		for _ in 0..20_000 {
+			let signature: Vec<_> =
+				(0..rng.below(6)).map(|_| KINDS[rng.below(9) as usize]).collect();
+			let mut payload = rng.words(12);
+			if rng.below(4) == 0 {
+				payload.truncate(rng.below(payload.len() as u32 + 1) as usize);
+			}
+			let _ = decode_args(&payload, &signature, &mut std::iter::empty());
+		}
+	}
+
+	#[test]
+	fn fuzz_event_stream() -> Result<(), WaytinierError> {
+		use std::io::Write;
+
+		use crate::wayland::{callback::Callback, display::Display, registry::Registry};
+
+		let mut rng = Rng(0x5eed_cafe_f00d_0002);
+		for _ in 0..1000 {
+			let (mut server, client) = UnixStream::pair()?;
+			let mut wlmm = MessageManager::from_stream(client)?;
+			let mut wlim = IdentManager::default();
+			Display::new_registered(&mut wlim);
+			Registry::new_registered(&mut wlim);
+			Callback::new_registered(&mut wlim);
+
+			let mut stream = vec![];
+			for _ in 0..rng.below(16) {
+				if rng.below(8) == 0 {
+					// plain garbage
+					stream.extend(rng.words(6));
+					continue;
+				}
+				let sender = match rng.below(8) {
+					0 => rng.next(),
+					_ => 1 + rng.below(3),
+				};
+				let opcode = rng.below(4);
+				let signature =
+					wlim.kind_of(Id(sender)).and_then(|k| k.event_signature(OpCode(opcode)));
+				let mut payload = match signature {
+					Some(sig) if rng.below(4) != 0 => {
+						sig.iter().flat_map(|k| rng.arg(*k)).collect()
+					}
+					_ => rng.words(8),
+				};
+				// and break some of the good ones again
+				if !payload.is_empty() && rng.below(4) == 0 {
+					let ix = rng.below(payload.len() as u32) as usize;
+					payload[ix] = rng.next() as u8;
+				}
+				let len = match rng.below(8) {
+					0 => rng.below(64),
+					_ => payload.len() as u32 + 8,
+				};
+				stream.extend(sender.to_ne_bytes());
+				stream.extend(((len << 16) | opcode).to_ne_bytes());
+				stream.extend(payload);
+			}
+			server.write_all(&stream)?;
+			drop(server);
+
+			// errors are fine, panics aren't
+			let _ = wlmm.read_socket();
+			while let Ok(Some(event)) = wlmm.next_event(&wlim) {
+				if wlim.dispatch(event).is_err() {
+					break;
+				}
+			}
+		}
+		Ok(())
 	}
 }
@@ -0,0 +1,212 @@
+use std::cell::Cell;
+
+use crate::{
+	DebugLevel, Rl, WaytinierError, rl,
+	wayland::{
+		AppRequest, Id, IdentManager, OpCode, Request, WaylandObject, WaylandObjectKind,
+		protocol::{xdg_surface, xdg_toplevel, xdg_wm_base},
+		registry::Registry,
+		surface::Surface,
+		wire::{WireArgument, WireRequest},
+	},
+};
+
+fn request(kind: &'static str, inner: WireRequest, opname: &'static str) -> Vec<AppRequest> {
+	vec![AppRequest::Request(Request {
+		inner,
+		opname,
+		kind,
+	})]
+}
+
+// the xdg_wm_base global, turns surfaces into windows
+pub(crate) struct WmBase {
+	pub(crate) id: Id,
+	pub(crate) version: u32,
+}
+
+impl WmBase {
+	pub(crate) fn new_bound(
+		wlim: &mut IdentManager,
+		registry: &Rl<Registry>,
+	) -> Result<(Vec<AppRequest>, Rl<Self>), WaytinierError> {
+		let wm_base = rl!(Self {
+			id: Id(0),
+			version: 1,
+		});
+		let id = wlim.new_id_registered(wm_base.clone());
+		wm_base.borrow_mut().id = id;
+		let (pending, version) = registry.borrow_mut().bind(
+			id,
+			WaylandObjectKind::XdgWmBase,
+			1..=xdg_wm_base::VERSION,
+		)?;
+		wm_base.borrow_mut().version = version;
+		Ok((pending, wm_base))
+	}
+
+	// gives the surface the xdg_surface role. it still needs a toplevel or
+	// popup role on top before it can be committed
+	pub(crate) fn get_xdg_surface(
+		&self,
+		wlim: &mut IdentManager,
+		surface: &Surface,
+	) -> (Vec<AppRequest>, Rl<XdgSurface>) {
+		let xdg = rl!(XdgSurface {
+			id: Id(0),
+			version: self.version,
+			serial: Cell::new(None),
+		});
+		let id = wlim.new_id_registered(xdg.clone());
+		xdg.borrow_mut().id = id;
+		let inner = xdg_wm_base::get_xdg_surface(self.id, id, surface.id);
+		(request(self.kind_str(), inner, "get_xdg_surface"), xdg)
+	}
+}
+
+impl WaylandObject for WmBase {
+	fn handle(
+		&self,
+		opcode: OpCode,
+		args: Vec<WireArgument>,
+	) -> Result<Vec<AppRequest>, WaytinierError> {
+		match xdg_wm_base::Event::from_args(opcode, args)? {
+			// the compositor checks we're still alive
+			xdg_wm_base::Event::Ping {
+				serial,
+			} => Ok(request(self.kind_str(), xdg_wm_base::pong(self.id, serial), "pong")),
+		}
+	}
+
+	fn kind(&self) -> WaylandObjectKind {
+		WaylandObjectKind::XdgWmBase
+	}
+
+	fn version(&self) -> u32 {
+		self.version
+	}
+}
+
+pub(crate) struct XdgSurface {
+	pub(crate) id: Id,
+	pub(crate) version: u32,
+	// the last configure that wasn't acked yet. the role objects got their
+	// part of the configure before it
+	pub(crate) serial: Cell<Option<u32>>,
+}
+
+impl XdgSurface {
+	pub(crate) fn get_toplevel(
+		&self,
+		wlim: &mut IdentManager,
+	) -> (Vec<AppRequest>, Rl<XdgToplevel>) {
+		let toplevel = rl!(XdgToplevel {
+			id: Id(0),
+			version: self.version,
+			size: Cell::new((0, 0)),
+			close_requested: Cell::new(false),
+		});
+		let id = wlim.new_id_registered(toplevel.clone());
+		toplevel.borrow_mut().id = id;
+		let inner = xdg_surface::get_toplevel(self.id, id);
+		(request(self.kind_str(), inner, "get_toplevel"), toplevel)
+	}
+
+	// the serial of a configure that came in since the last call
+	pub(crate) fn take_configure(&self) -> Option<u32> {
+		self.serial.take()
+	}
+
+	pub(crate) fn ack_configure(&self, serial: u32) -> Vec<AppRequest> {
+		request(self.kind_str(), xdg_surface::ack_configure(self.id, serial), "ack_configure")
+	}
+
+	pub(crate) fn destroy(&self) -> Vec<AppRequest> {
+		request(self.kind_str(), xdg_surface::destroy(self.id), "destroy")
+	}
+}
+
+impl WaylandObject for XdgSurface {
+	fn handle(
+		&self,
+		opcode: OpCode,
+		args: Vec<WireArgument>,
+	) -> Result<Vec<AppRequest>, WaytinierError> {
+		match xdg_surface::Event::from_args(opcode, args)? {
+			xdg_surface::Event::Configure {
+				serial,
+			} => {
+				self.serial.set(Some(serial));
+				Ok(vec![])
+			}
+		}
+	}
+
+	fn kind(&self) -> WaylandObjectKind {
+		WaylandObjectKind::XdgSurface
+	}
+
+	fn version(&self) -> u32 {
+		self.version
+	}
+}
+
+pub(crate) struct XdgToplevel {
+	pub(crate) id: Id,
+	pub(crate) version: u32,
+	// the size from the last configure, zero means we pick
+	pub(crate) size: Cell<(i32, i32)>,
+	pub(crate) close_requested: Cell<bool>,
+}
+
+impl XdgToplevel {
+	pub(crate) fn set_title(&self, title: &str) -> Vec<AppRequest> {
+		request(self.kind_str(), xdg_toplevel::set_title(self.id, title), "set_title")
+	}
+
+	pub(crate) fn set_app_id(&self, app_id: &str) -> Vec<AppRequest> {
+		request(self.kind_str(), xdg_toplevel::set_app_id(self.id, app_id), "set_app_id")
+	}
+
+	pub(crate) fn destroy(&self) -> Vec<AppRequest> {
+		request(self.kind_str(), xdg_toplevel::destroy(self.id), "destroy")
+	}
+}
+
+impl WaylandObject for XdgToplevel {
+	fn handle(
+		&self,
+		opcode: OpCode,
+		args: Vec<WireArgument>,
+	) -> Result<Vec<AppRequest>, WaytinierError> {
+		match xdg_toplevel::Event::from_args(opcode, args)? {
+			xdg_toplevel::Event::Configure {
+				width,
+				height,
+				..
+			} => self.size.set((width, height)),
+			xdg_toplevel::Event::Close => self.close_requested.set(true),
+			xdg_toplevel::Event::ConfigureBounds {
+				width,
+				height,
+			} => {
+				return Ok(vec![AppRequest::DebugMessage(
+					DebugLevel::Verbose,
+					format!("toplevel {} bounds are {width}x{height}", self.id),
+				)]);
+			}
+			xdg_toplevel::Event::WmCapabilities {
+				..
+			} => (),
+		}
+		Ok(vec![])
+	}
+
+	fn kind(&self) -> WaylandObjectKind {
+		WaylandObjectKind::XdgToplevel
+	}
+
+	fn version(&self) -> u32 {
+		self.version
+	}
+}